@@ -0,0 +1,382 @@
+use crate::discord::{Context, Data, Error};
+use crate::utils;
+use anyhow::{Context as AnyhowContext, Result};
+use poise::serenity_prelude::{ChannelId, CreateAllowedMentions, CreateMessage, MessageBuilder};
+use poise::CommandInteractionType;
+use serde::{Deserialize, Serialize};
+use serde_yaml::Value;
+use std::{collections::BTreeSet, path::PathBuf};
+use tokio::{io::AsyncWriteExt, sync::Mutex};
+
+/// How many lines of the state diff are mirrored to Discord at most.
+const MAX_MIRRORED_DIFF: usize = 10;
+/// Mirrored messages have to stay below Discord's limit of 2000 characters.
+const MAX_MSG_LEN: usize = 1900;
+
+/// What came of a command.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Outcome {
+    Success,
+    Failed(String),
+    /// The user lacked the permission.
+    Denied,
+}
+
+impl std::fmt::Display for Outcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Success => write!(f, "succeeded"),
+            Self::Failed(error) => write!(f, "failed: {}", error),
+            Self::Denied => write!(f, "denied"),
+        }
+    }
+}
+
+/// A privileged command someone ran.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Entry {
+    /// Unix timestamp of when the command finished.
+    pub at: i64,
+    /// Discord user who ran it.
+    pub user: u64,
+    pub user_name: String,
+    /// Name including the parent commands, e.g. `envs set`.
+    pub command: String,
+    /// The command with all its arguments as entered.
+    pub invocation: String,
+    pub outcome: Outcome,
+    /// Changes of the bot state, `-` for removed and `+` for added lines.
+    pub diff: Vec<String>,
+}
+
+/// Append-only trail of privileged commands, saved as JSON lines.
+#[derive(Debug)]
+pub struct Audit {
+    path: PathBuf,
+    /// Where entries are mirrored to.
+    channel: Option<ChannelId>,
+    /// Keeps appended lines from interleaving.
+    file: Mutex<()>,
+}
+
+/// Bot state before a command ran, kept as invocation data.
+struct Before(BTreeSet<String>);
+
+/// Marks an invocation whose denial has been recorded, kept as invocation data.
+struct Denied;
+
+impl Audit {
+    pub fn new(channel: Option<ChannelId>) -> Self {
+        Self {
+            path: PathBuf::from(
+                std::env::var("BOT_AUDIT_LOG").unwrap_or_else(|_| "audit.jsonl".to_string()),
+            ),
+            channel,
+            file: Mutex::new(()),
+        }
+    }
+
+    /// Remembers the state before a privileged command runs, to record what it changed.
+    pub async fn before(ctx: Context<'_>) {
+        if ctx.command().checks.is_empty() {
+            return;
+        }
+        ctx.set_invocation_data(Before(flatten_states(ctx.data()).await))
+            .await;
+    }
+
+    /// Records the `outcome` of a privileged command.
+    pub async fn record(ctx: Context<'_>, outcome: Outcome) {
+        if ctx.command().checks.is_empty() {
+            return;
+        }
+        let before = match ctx.invocation_data::<Before>().await {
+            Some(before) => before.0.clone(),
+            None => BTreeSet::new(),
+        };
+        let diff = match outcome {
+            Outcome::Denied => Vec::new(),
+            _ => {
+                let after = flatten_states(ctx.data()).await;
+                let removed = before.difference(&after).map(|line| format!("- {}", line));
+                let added = after.difference(&before).map(|line| format!("+ {}", line));
+                removed.chain(added).collect()
+            }
+        };
+
+        let entry = Entry {
+            at: chrono::Utc::now().timestamp(),
+            user: ctx.author().id.get(),
+            user_name: ctx.author().tag(),
+            command: ctx.command().qualified_name.clone(),
+            invocation: ctx.invocation_string(),
+            outcome,
+            diff,
+        };
+
+        let audit = &ctx.data().audit;
+        if let Err(e) = audit.append(&entry).await {
+            log::error!("Failed to write audit log: {}", e);
+        }
+        if let Some(channel) = audit.channel {
+            let msg = CreateMessage::new()
+                .content(create_entry_msg(&entry, MAX_MIRRORED_DIFF, MAX_MSG_LEN))
+                .allowed_mentions(CreateAllowedMentions::new());
+            if let Err(e) = channel.send_message(ctx, msg).await {
+                log::error!("Failed to mirror audit log: {}", e);
+            }
+        }
+    }
+
+    async fn append(&self, entry: &Entry) -> Result<()> {
+        let mut line = serde_json::to_string(entry).context("Failed to serialize entry")?;
+        line.push('\n');
+
+        let _guard = self.file.lock().await;
+        let mut file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .await?;
+        file.write_all(line.as_bytes()).await?;
+        file.sync_all().await?;
+        Ok(())
+    }
+
+    /// All entries, oldest first.
+    pub async fn entries(&self) -> Result<Vec<Entry>> {
+        let content = match tokio::fs::read_to_string(&self.path).await {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(content
+            .lines()
+            .filter_map(|line| match serde_json::from_str(line) {
+                Ok(entry) => Some(entry),
+                Err(e) => {
+                    log::warn!("Skipped malformed audit log entry: {}", e);
+                    None
+                }
+            })
+            .collect())
+    }
+}
+
+/// Describes `entry` in a message of at most `max_len` bytes, with up to `max_diff` lines of its diff.
+pub fn create_entry_msg(entry: &Entry, max_diff: usize, max_len: usize) -> String {
+    // Cut long invocations and errors, so that some of the diff still fits.
+    let part_len = max_len / 4;
+    let mut header = MessageBuilder::new();
+    header
+        .push(format!("<t:{}:f> ", entry.at))
+        .push_bold_safe(&entry.user_name)
+        .push(" ran ")
+        .push_mono_safe(utils::truncate(&entry.invocation, part_len))
+        .push_line(format!(
+            " and {}",
+            utils::truncate(&entry.outcome.to_string(), part_len)
+        ));
+    let header = header.build();
+    if entry.diff.is_empty() {
+        return header;
+    }
+
+    // Show as many lines of the diff as fit.
+    let lines = entry
+        .diff
+        .iter()
+        .map(|line| utils::truncate(line, part_len))
+        .collect::<Vec<_>>();
+    let mut shown = lines.len().min(max_diff);
+    loop {
+        let mut diff = lines[..shown].to_vec();
+        if entry.diff.len() > shown {
+            diff.push(format!("... and {} more", entry.diff.len() - shown));
+        }
+        let msg = MessageBuilder::new()
+            .push(&header)
+            .push_codeblock_safe(diff.join("\n"), Some("diff"))
+            .build();
+        if msg.len() <= max_len || shown == 0 {
+            return msg;
+        }
+        shown -= 1;
+    }
+}
+
+/// The states of all instances as one line per value, prefixed by the instance if there are several.
+async fn flatten_states(data: &Data) -> BTreeSet<String> {
+    let mut lines = BTreeSet::new();
+    for (name, instance) in &data.instances {
+        let prefix = match data.instances.len() {
+            1 => String::new(),
+            _ => name.clone(),
+        };
+        match instance.state.lock().await.masked() {
+            Ok(state) => flatten(&prefix, &state, &mut lines),
+            Err(e) => log::warn!("Failed to serialize state for the audit log: {}", e),
+        }
+    }
+    lines
+}
+
+/// Adds `value` to `lines` as `path = value`. Sequence items are kept on one line each,
+/// so inserting one doesn't change the lines of all that follow.
+fn flatten(path: &str, value: &Value, lines: &mut BTreeSet<String>) {
+    match value {
+        Value::Mapping(mapping) => {
+            for (key, value) in mapping {
+                let key = match key {
+                    Value::String(key) => key.clone(),
+                    key => inline(key),
+                };
+                let path = match path.is_empty() {
+                    true => key,
+                    false => format!("{}.{}", path, key),
+                };
+                flatten(&path, value, lines);
+            }
+        }
+        Value::Sequence(items) => {
+            for item in items {
+                lines.insert(format!("{}[] = {}", path, inline(item)));
+            }
+        }
+        Value::Tagged(tagged) => {
+            // Enum variants, e.g. `!Branch master`.
+            let variant = tagged.tag.to_string();
+            let path = format!("{}.{}", path, variant.trim_start_matches('!'));
+            flatten(&path, &tagged.value, lines)
+        }
+        value => {
+            lines.insert(format!("{} = {}", path, inline(value)));
+        }
+    }
+}
+
+/// `value` on a single line.
+fn inline(value: &Value) -> String {
+    match value {
+        Value::String(value) => value.clone(),
+        value => serde_json::to_string(value).unwrap_or_default(),
+    }
+}
+
+/// Parses `since` as a time span back from now like `12h` or `7d`, or a date like `2024-01-31`.
+/// Returns the unix timestamp it refers to.
+pub fn parse_since(since: &str) -> Option<i64> {
+    let now = chrono::Utc::now();
+    if let Ok(date) = chrono::NaiveDate::parse_from_str(since, "%Y-%m-%d") {
+        return Some(date.and_hms_opt(0, 0, 0)?.and_utc().timestamp());
+    }
+
+    let unit = since.chars().last()?;
+    let amount: i64 = since[..since.len() - unit.len_utf8()].parse().ok()?;
+    // Spans too long to be represented are rejected like invalid ones.
+    let span = match unit {
+        'm' => chrono::Duration::try_minutes(amount),
+        'h' => chrono::Duration::try_hours(amount),
+        'd' => chrono::Duration::try_days(amount),
+        'w' => chrono::Duration::try_weeks(amount),
+        _ => return None,
+    }?;
+    Some(now.checked_sub_signed(span)?.timestamp())
+}
+
+/// Records privileged commands which failed or were denied.
+pub async fn on_error(error: &poise::FrameworkError<'_, Data, Error>) {
+    match error {
+        poise::FrameworkError::Command { error, ctx, .. } => {
+            Audit::record(*ctx, Outcome::Failed(error.to_string())).await
+        }
+        poise::FrameworkError::CommandCheckFailed { ctx, .. } => {
+            // Checks also run for every keystroke while autocompleting, which invokes nothing.
+            let autocomplete = matches!(
+                ctx,
+                poise::Context::Application(ctx)
+                    if ctx.interaction_type == CommandInteractionType::Autocomplete
+            );
+            if autocomplete || ctx.invocation_data::<Denied>().await.is_some() {
+                return;
+            }
+            ctx.set_invocation_data(Denied).await;
+            Audit::record(*ctx, Outcome::Denied).await
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn flattened(yaml: &str) -> Vec<String> {
+        let mut lines = BTreeSet::new();
+        flatten("", &serde_yaml::from_str(yaml).unwrap(), &mut lines);
+        lines.into_iter().collect()
+    }
+
+    #[test]
+    fn flattens_nested_values() {
+        let yaml =
+            "rev: !Branch master\nseamless: true\nrestart_policy: !OnFailure\n  max_retries: 3\n";
+        assert_eq!(
+            flattened(yaml),
+            [
+                "restart_policy.OnFailure.max_retries = 3",
+                "rev.Branch = master",
+                "seamless = true",
+            ]
+        );
+    }
+
+    #[test]
+    fn flattens_sequence_items_into_lines_of_their_own() {
+        let yaml = "args:\n- --no-auth\n- --sql-log-mode=trace\nschedule:\n- id: 1\n  next: 100\n";
+        assert_eq!(
+            flattened(yaml),
+            [
+                "args[] = --no-auth",
+                "args[] = --sql-log-mode=trace",
+                r#"schedule[] = {"id":1,"next":100}"#,
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_dates() {
+        assert_eq!(parse_since("2024-01-31"), Some(1706659200));
+        assert_eq!(parse_since("2024-02-30"), None);
+    }
+
+    #[test]
+    fn parses_spans() {
+        let now = chrono::Utc::now().timestamp();
+        let ago = |since| now - parse_since(since).unwrap();
+        assert!((12 * 60 * 60..12 * 60 * 60 + 5).contains(&ago("12h")));
+        assert!((7 * 24 * 60 * 60..7 * 24 * 60 * 60 + 5).contains(&ago("1w")));
+        assert!(ago("30m") >= 30 * 60);
+        assert_eq!(parse_since("12"), None);
+        assert_eq!(parse_since("h"), None);
+        assert_eq!(parse_since("3y"), None);
+        assert_eq!(parse_since("9999999999999w"), None);
+    }
+
+    #[test]
+    fn entry_messages_fit() {
+        let entry = Entry {
+            at: 0,
+            user: 1,
+            user_name: "alice".to_string(),
+            command: "exec".to_string(),
+            invocation: format!("/exec command:{}", "x".repeat(3000)),
+            outcome: Outcome::Failed("y".repeat(3000)),
+            diff: (0..100).map(|i| format!("+ args[] = {}", i)).collect(),
+        };
+        let msg = create_entry_msg(&entry, 10, 1900);
+        assert!(msg.len() <= 1900);
+        assert!(msg.contains("+ args[] = 0"));
+    }
+}
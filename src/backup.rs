@@ -0,0 +1,304 @@
+use crate::{server::Server, state::State};
+use anyhow::{Context, Result};
+use chrono::{NaiveDateTime, TimeZone, Utc};
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+use tokio::{process::Command, sync::Mutex};
+
+/// Format of the time backups are named after, precise enough for backups in quick succession.
+const TIME_FORMAT: &str = "%Y%m%d-%H%M%S%.3f";
+/// Length of a time formatted with [`TIME_FORMAT`].
+const TIME_LEN: usize = 19;
+/// How often it is checked whether a scheduled backup is due.
+const TICK: Duration = Duration::from_secs(10 * 60);
+/// Part of the userdata which is backed up.
+const SERVER_DIR: &str = "server";
+/// The database, relative to [`SERVER_DIR`]. Copied through sqlite's online backup.
+const DATABASE: &str = "saves/db.sqlite";
+
+/// How many backups are kept.
+#[derive(Debug, Clone, Copy)]
+pub struct Retention {
+    /// Most recent backups which are kept no matter what.
+    pub keep_last: usize,
+    /// Days for which the last backup of the day is kept.
+    pub keep_daily: usize,
+}
+
+/// A snapshot of the userdata of a gameserver.
+#[derive(Debug, Clone)]
+pub struct Backup {
+    /// Name of the backup directory, e.g. `20240131-040000.000-scheduled`.
+    pub name: String,
+    /// Unix timestamp of when it has been made.
+    pub created: i64,
+    /// Why it has been made, e.g. `scheduled` or `upload`.
+    pub reason: String,
+    path: PathBuf,
+}
+
+impl Backup {
+    fn parse(path: PathBuf) -> Option<Self> {
+        let name = path.file_name()?.to_str()?.to_string();
+        let time = name.get(..TIME_LEN)?;
+        let created = NaiveDateTime::parse_from_str(time, TIME_FORMAT).ok()?;
+        let reason = name.get(TIME_LEN + 1..).unwrap_or_default().to_string();
+        Some(Self {
+            created: Utc.from_utc_datetime(&created).timestamp(),
+            name,
+            reason,
+            path,
+        })
+    }
+}
+
+/// Backs up the userdata in `userdata` into a new directory in `backups`.
+pub async fn create(userdata: &Path, backups: &Path, reason: &str) -> Result<Backup> {
+    let name = format!("{}-{}", Utc::now().format(TIME_FORMAT), reason);
+    let path = backups.join(&name);
+    log::info!("Backing up {} to {}...", userdata.display(), path.display());
+    tokio::fs::create_dir_all(backups).await?;
+    // Fails if the backup exists already instead of mixing two into one.
+    tokio::fs::create_dir(&path)
+        .await
+        .with_context(|| format!("Failed to create backup {}", name))?;
+
+    let source = userdata.join(SERVER_DIR);
+    let target = path.join(SERVER_DIR);
+    copy_dir(&source, &target, &database_files(&source))
+        .await
+        .context("Failed to copy userdata")?;
+
+    let database = source.join(DATABASE);
+    if database.exists() {
+        // Consistent even while the gameserver is writing to it.
+        let mut cmd = Command::new("sqlite3");
+        cmd.arg(&database);
+        let target = target.join(DATABASE).display().to_string();
+        cmd.arg(format!(".backup '{}'", target.replace('\'', "''")));
+        crate::utils::execute("sqlite3", cmd)
+            .await
+            .context("Failed to back up database")?;
+    }
+
+    Backup::parse(path).context("Backup has an invalid name")
+}
+
+/// Replaces the userdata in `userdata` with `backup`. The gameserver must not be running.
+pub async fn restore(userdata: &Path, backup: &Backup) -> Result<()> {
+    log::info!("Restoring {} from {}...", userdata.display(), backup.name);
+
+    let target = userdata.join(SERVER_DIR);
+    if target.exists() {
+        tokio::fs::remove_dir_all(&target)
+            .await
+            .context("Failed to remove userdata")?;
+    }
+    copy_dir(&backup.path.join(SERVER_DIR), &target, &HashSet::new())
+        .await
+        .context("Failed to copy backup")
+}
+
+/// All backups in `backups`, newest first.
+pub async fn list(backups: &Path) -> Result<Vec<Backup>> {
+    let mut list = Vec::new();
+    let mut entries = match tokio::fs::read_dir(backups).await {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(list),
+        Err(e) => return Err(e.into()),
+    };
+    while let Some(entry) = entries.next_entry().await? {
+        if let Some(backup) = Backup::parse(entry.path()) {
+            list.push(backup);
+        }
+    }
+    list.sort_by(|a, b| b.name.cmp(&a.name));
+    Ok(list)
+}
+
+/// Deletes the backups in `backups` which `retention` doesn't keep, except for `spare`.
+pub async fn prune(backups: &Path, retention: Retention, spare: Option<&Backup>) -> Result<()> {
+    let mut days = HashSet::new();
+    for (i, backup) in list(backups).await?.into_iter().enumerate() {
+        // Newest first, so the first one of each day is the last one made that day.
+        let day = backup.name[..8].to_string();
+        let daily = days.len() < retention.keep_daily && days.insert(day);
+        let spared = spare.is_some_and(|spare| spare.name == backup.name);
+        if i < retention.keep_last || daily || spared {
+            continue;
+        }
+
+        log::info!("Deleting backup {}...", backup.name);
+        tokio::fs::remove_dir_all(&backup.path)
+            .await
+            .with_context(|| format!("Failed to delete backup {}", backup.name))?;
+    }
+    Ok(())
+}
+
+/// Backs up the userdata of `server` every `interval` and applies its retention afterwards.
+pub async fn run(state: Arc<Mutex<State>>, server: Arc<Mutex<Server>>, interval: Duration) {
+    let mut tick = tokio::time::interval(TICK);
+    loop {
+        tick.tick().await;
+
+        let (userdata, backups, retention) = {
+            let server = server.lock().await;
+            let state = state.lock().await;
+            (
+                server.userdata(state.cargo_args()),
+                server.paths().backups.clone(),
+                server.retention(),
+            )
+        };
+        if !userdata.join(SERVER_DIR).exists() {
+            continue;
+        }
+
+        let last = match list(&backups).await {
+            Ok(list) => list.first().map(|backup| backup.created),
+            Err(e) => {
+                log::error!("Failed to list backups: {}", e);
+                continue;
+            }
+        };
+        let now = Utc::now().timestamp();
+        if last.is_some_and(|last| now - last < interval.as_secs() as i64) {
+            continue;
+        }
+
+        if let Err(e) = create(&userdata, &backups, "scheduled").await {
+            log::error!("Failed to make scheduled backup: {:?}", e);
+            continue;
+        }
+        if let Err(e) = prune(&backups, retention, None).await {
+            log::error!("Failed to prune backups: {:?}", e);
+        }
+    }
+}
+
+/// The database and the files sqlite keeps next to it, which are not copied as they are.
+fn database_files(server: &Path) -> HashSet<PathBuf> {
+    let database = server.join(DATABASE);
+    ["", "-wal", "-shm", "-journal"]
+        .iter()
+        .map(|suffix| {
+            let mut path = database.clone().into_os_string();
+            path.push(suffix);
+            PathBuf::from(path)
+        })
+        .collect()
+}
+
+/// Copies the directory `from` to `to` recursively, leaving out `skip`.
+async fn copy_dir(from: &Path, to: &Path, skip: &HashSet<PathBuf>) -> Result<()> {
+    let mut dirs = vec![(from.to_path_buf(), to.to_path_buf())];
+    while let Some((from, to)) = dirs.pop() {
+        tokio::fs::create_dir_all(&to).await?;
+        let mut entries = tokio::fs::read_dir(&from).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            if skip.contains(&path) {
+                continue;
+            }
+            let target = to.join(entry.file_name());
+            if entry.file_type().await?.is_dir() {
+                dirs.push((path, target));
+            } else {
+                tokio::fs::copy(&path, &target).await?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A directory in the temp dir with a backup for each of `names`.
+    fn backups(test: &str, names: &[&str]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "veloren-bot-backups-{}-{}",
+            test,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        for name in names {
+            std::fs::create_dir_all(dir.join(name)).unwrap();
+        }
+        dir
+    }
+
+    async fn names(backups: &Path) -> Vec<String> {
+        list(backups)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|backup| backup.name)
+            .collect()
+    }
+
+    #[test]
+    fn parses_names() {
+        let backup = Backup::parse(PathBuf::from("20240131-040000.123-scheduled")).unwrap();
+        assert_eq!(backup.created, 1706673600);
+        assert_eq!(backup.reason, "scheduled");
+        assert!(Backup::parse(PathBuf::from("20240131-040000-scheduled")).is_none());
+        assert!(Backup::parse(PathBuf::from("notes")).is_none());
+    }
+
+    #[tokio::test]
+    async fn prune_keeps_last_and_daily() {
+        let dir = backups(
+            "retention",
+            &[
+                "20240129-040000.000-scheduled",
+                "20240130-040000.000-scheduled",
+                "20240130-120000.000-upload",
+                "20240131-040000.000-scheduled",
+                "20240131-080000.000-restore",
+                "20240131-120000.000-scheduled",
+            ],
+        );
+        let retention = Retention {
+            keep_last: 2,
+            keep_daily: 2,
+        };
+        prune(&dir, retention, None).await.unwrap();
+        assert_eq!(
+            names(&dir).await,
+            [
+                "20240131-120000.000-scheduled",
+                "20240131-080000.000-restore",
+                "20240130-120000.000-upload",
+            ]
+        );
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn prune_spares_backup() {
+        let dir = backups(
+            "spare",
+            &[
+                "20240130-040000.000-scheduled",
+                "20240131-040000.000-scheduled",
+            ],
+        );
+        let retention = Retention {
+            keep_last: 1,
+            keep_daily: 0,
+        };
+        let spare = Backup::parse(dir.join("20240130-040000.000-scheduled")).unwrap();
+        prune(&dir, retention, Some(&spare)).await.unwrap();
+        assert_eq!(names(&dir).await.len(), 2);
+        prune(&dir, retention, None).await.unwrap();
+        assert_eq!(names(&dir).await, ["20240131-040000.000-scheduled"]);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -1,20 +1,59 @@
 use crate::discord::Context;
 use crate::discord::Error;
+use crate::state::Role;
 
-/// Checks whether the user is in the admin list.
-pub async fn is_admin(ctx: Context<'_>) -> Result<bool, Error> {
-    let state = ctx.data().state.lock().await;
-    let settings = ctx.data().settings.lock().await;
+/// Checks whether the user has at least `role`, themselves or through one of their Discord roles.
+/// The bot owner is allowed to do anything.
+/// Roles are granted for all instances at once and kept in the state of the main one.
+async fn has_role(ctx: Context<'_>, role: Role) -> Result<bool, Error> {
+    if ctx.data().settings.lock().await.owner == ctx.author().id.get() {
+        return Ok(true);
+    }
+
+    let roles = match ctx.author_member().await {
+        Some(member) => member.roles.clone(),
+        None => Vec::new(),
+    };
+    let granted = ctx
+        .data()
+        .main()
+        .state
+        .lock()
+        .await
+        .role(ctx.author().id, &roles);
 
-    if state.admins().contains(&ctx.author().id) || settings.owner == ctx.author().id.get() {
+    if granted >= Some(role) {
         Ok(true)
     } else {
-        ctx.say("You need to be an Admin to execute this command.")
-            .await?;
+        ctx.say(format!(
+            "You need the `{}` role to execute this command.",
+            role
+        ))
+        .await?;
         Ok(false)
     }
 }
 
+/// Checks whether the user may see the configuration and logs.
+pub async fn is_viewer(ctx: Context<'_>) -> Result<bool, Error> {
+    has_role(ctx, Role::Viewer).await
+}
+
+/// Checks whether the user may start, stop and restart the server.
+pub async fn is_operator(ctx: Context<'_>) -> Result<bool, Error> {
+    has_role(ctx, Role::Operator).await
+}
+
+/// Checks whether the user may change what is deployed and how.
+pub async fn is_deployer(ctx: Context<'_>) -> Result<bool, Error> {
+    has_role(ctx, Role::Deployer).await
+}
+
+/// Checks whether the user may manage files and roles.
+pub async fn is_admin(ctx: Context<'_>) -> Result<bool, Error> {
+    has_role(ctx, Role::Admin).await
+}
+
 /// Checks whether the user is the bot owner.
 pub async fn is_owner(ctx: Context<'_>) -> Result<bool, Error> {
     let settings = ctx.data().settings.lock().await;
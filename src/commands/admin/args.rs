@@ -5,7 +5,7 @@ use poise::serenity_prelude::MessageBuilder;
 /// Manage arguments passed to the gameserver.
 #[poise::command(
     slash_command,
-    check = "crate::checks::is_admin",
+    check = "crate::checks::is_viewer",
     subcommands("add", "remove", "list", "reset")
 )]
 pub async fn args(_ctx: Context<'_>) -> Result<(), Error> {
@@ -14,12 +14,16 @@ pub async fn args(_ctx: Context<'_>) -> Result<(), Error> {
 }
 
 /// Add argument passed to the gameserver.
-#[poise::command(slash_command, check = "crate::checks::is_admin")]
+#[poise::command(slash_command, check = "crate::checks::is_deployer")]
 pub async fn add(
     ctx: Context<'_>,
     #[description = "argument to add"] argument: String,
+    #[description = "Gameserver instance (default the main one)"]
+    #[autocomplete = "crate::commands::autocomplete_instance"]
+    instance: Option<String>,
 ) -> Result<(), Error> {
-    let mut state = ctx.data().state.lock().await;
+    let instance = crate::commands::instance(ctx, instance).await?;
+    let mut state = instance.state.lock().await;
 
     state.add_arg(&argument).await?;
     ctx.say(format!("Added `{}` as gameserver argument.", argument))
@@ -29,12 +33,16 @@ pub async fn add(
 }
 
 /// Remove argument passed to the gameserver.
-#[poise::command(slash_command, check = "crate::checks::is_admin")]
+#[poise::command(slash_command, check = "crate::checks::is_deployer")]
 pub async fn remove(
     ctx: Context<'_>,
     #[description = "argument to remove"] argument: String,
+    #[description = "Gameserver instance (default the main one)"]
+    #[autocomplete = "crate::commands::autocomplete_instance"]
+    instance: Option<String>,
 ) -> Result<(), Error> {
-    let mut state = ctx.data().state.lock().await;
+    let instance = crate::commands::instance(ctx, instance).await?;
+    let mut state = instance.state.lock().await;
 
     state.remove_arg(&argument).await?;
     ctx.say(format!(
@@ -47,9 +55,15 @@ pub async fn remove(
 }
 
 /// List arguments passed to the gameserver.
-#[poise::command(slash_command, check = "crate::checks::is_admin")]
-pub async fn list(ctx: Context<'_>) -> Result<(), Error> {
-    let state = ctx.data().state.lock().await;
+#[poise::command(slash_command, check = "crate::checks::is_viewer")]
+pub async fn list(
+    ctx: Context<'_>,
+    #[description = "Gameserver instance (default the main one)"]
+    #[autocomplete = "crate::commands::autocomplete_instance"]
+    instance: Option<String>,
+) -> Result<(), Error> {
+    let instance = crate::commands::instance(ctx, instance).await?;
+    let state = instance.state.lock().await;
 
     let mut response = MessageBuilder::new();
     response.push_bold_line("Gameserver Arguments:");
@@ -67,10 +81,28 @@ pub async fn list(ctx: Context<'_>) -> Result<(), Error> {
 }
 
 /// Reset arguments passed to the gameserver to default.
-#[poise::command(slash_command, check = "crate::checks::is_admin")]
-pub async fn reset(ctx: Context<'_>) -> Result<(), Error> {
-    let mut state = ctx.data().state.lock().await;
+#[poise::command(slash_command, check = "crate::checks::is_deployer")]
+pub async fn reset(
+    ctx: Context<'_>,
+    #[description = "Gameserver instance (default the main one)"]
+    #[autocomplete = "crate::commands::autocomplete_instance"]
+    instance: Option<String>,
+) -> Result<(), Error> {
+    let instance = crate::commands::instance(ctx, instance).await?;
+
+    let args: Vec<_> = instance.state.lock().await.args().iter().cloned().collect();
+    if !args.is_empty() {
+        let consequences = MessageBuilder::new()
+            .push("All gameserver arguments will be removed: ")
+            .push_mono_safe(args.join(" "))
+            .push(". Applies on the next restart.")
+            .build();
+        if !crate::commands::confirm(ctx, &consequences).await? {
+            return Ok(());
+        }
+    }
 
+    let mut state = instance.state.lock().await;
     state.reset_args().await?;
     ctx.say("Reset all gameserver arguments to default.")
         .await?;
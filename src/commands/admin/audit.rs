@@ -0,0 +1,70 @@
+use crate::audit::{create_entry_msg, parse_since};
+use crate::discord::Context;
+use crate::discord::Error;
+use poise::serenity_prelude::{CreateAllowedMentions, User};
+use poise::CreateReply;
+
+/// How many entries are shown at most.
+const MAX_ENTRIES: usize = 10;
+/// How many lines of the state diff are shown per entry.
+const MAX_DIFF: usize = 4;
+/// Discord refuses messages longer than 2000 characters. Leave some room for formatting.
+const MAX_MSG_LEN: usize = 1900;
+
+/// Show who ran which privileged command, newest first.
+#[poise::command(slash_command, check = "crate::checks::is_admin")]
+pub async fn audit(
+    ctx: Context<'_>,
+    #[description = "Only commands run by this user"] user: Option<User>,
+    #[description = "Only this command, e.g. rev or envs set"] command: Option<String>,
+    #[description = "Only since a date (2024-01-31) or time span ago (12h, 7d)"] since: Option<
+        String,
+    >,
+) -> Result<(), Error> {
+    let since = match since.as_deref().map(parse_since) {
+        Some(None) => {
+            ctx.say(
+                "`since` has to be a date like `2024-01-31` or a time span like `12h` or `7d`.",
+            )
+            .await?;
+            return Ok(());
+        }
+        Some(since) => since,
+        None => None,
+    };
+
+    let entries = ctx.data().audit.entries().await?;
+    let entries = entries
+        .iter()
+        .rev()
+        .filter(|entry| user.as_ref().is_none_or(|user| entry.user == user.id.get()))
+        .filter(|entry| {
+            command
+                .as_ref()
+                .is_none_or(|command| entry.command.starts_with(command.trim_start_matches('/')))
+        })
+        .filter(|entry| since.is_none_or(|since| entry.at >= since))
+        .take(MAX_ENTRIES);
+
+    let mut response = String::new();
+    for entry in entries {
+        // The newest entry is cut to fit, the others are left out if they don't.
+        let msg = create_entry_msg(entry, MAX_DIFF, MAX_MSG_LEN);
+        if response.len() + msg.len() > MAX_MSG_LEN {
+            break;
+        }
+        response.push_str(&msg);
+    }
+    if response.is_empty() {
+        response = "*No matching commands recorded.*".to_string();
+    }
+
+    ctx.send(
+        CreateReply::default()
+            .content(response)
+            .allowed_mentions(CreateAllowedMentions::new()),
+    )
+    .await?;
+
+    Ok(())
+}
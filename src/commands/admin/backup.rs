@@ -0,0 +1,224 @@
+use crate::backup;
+use crate::discord::Context;
+use crate::discord::Error;
+use poise::serenity_prelude::MessageBuilder;
+use std::collections::BTreeSet;
+
+/// How many backups are listed at most.
+const MAX_LISTED: usize = 15;
+
+/// Manage backups of the gameserver userdata.
+#[poise::command(
+    slash_command,
+    check = "crate::checks::is_admin",
+    subcommands("list", "create", "restore")
+)]
+pub async fn backup(_ctx: Context<'_>) -> Result<(), Error> {
+    // Discord doesn't allow root commands to be invoked. Only Subcommands.
+    Ok(())
+}
+
+/// Suggests the backups of all instances matching `partial`, newest first.
+async fn autocomplete_backup(ctx: Context<'_>, partial: &str) -> Vec<String> {
+    let mut names = BTreeSet::new();
+    for instance in ctx.data().instances.values() {
+        let backups = instance.server.lock().await.paths().backups.clone();
+        if let Ok(list) = backup::list(&backups).await {
+            names.extend(list.into_iter().map(|backup| backup.name));
+        }
+    }
+
+    names
+        .into_iter()
+        .rev()
+        .filter(|name| name.starts_with(partial))
+        .take(25)
+        .collect()
+}
+
+/// List the most recent backups.
+#[poise::command(slash_command, check = "crate::checks::is_admin")]
+pub async fn list(
+    ctx: Context<'_>,
+    #[description = "Gameserver instance (default the main one)"]
+    #[autocomplete = "crate::commands::autocomplete_instance"]
+    instance: Option<String>,
+) -> Result<(), Error> {
+    let instance = crate::commands::instance(ctx, instance).await?;
+    let backups = instance.server.lock().await.paths().backups.clone();
+
+    let list = backup::list(&backups).await?;
+    let mut response = MessageBuilder::new();
+    response
+        .push("Backups of ")
+        .push_bold_safe(&instance.name)
+        .push_line(":");
+    for backup in list.iter().take(MAX_LISTED) {
+        response
+            .push_mono_safe(&backup.name)
+            .push_line(format!(" {} <t:{}:R>", backup.reason, backup.created));
+    }
+    if list.len() > MAX_LISTED {
+        response.push_italic_line(format!("and {} older ones.", list.len() - MAX_LISTED));
+    }
+    if list.is_empty() {
+        response.push_italic_line("No backups yet.");
+    }
+    ctx.say(response.build()).await?;
+
+    Ok(())
+}
+
+/// Back up the userdata now.
+#[poise::command(slash_command, check = "crate::checks::is_admin")]
+pub async fn create(
+    ctx: Context<'_>,
+    #[description = "Gameserver instance (default the main one)"]
+    #[autocomplete = "crate::commands::autocomplete_instance"]
+    instance: Option<String>,
+) -> Result<(), Error> {
+    let instance = crate::commands::instance(ctx, instance).await?;
+    ctx.defer().await?;
+    let server = instance.server.lock().await;
+    let state = instance.state.lock().await;
+
+    match server.backup(state.cargo_args(), "manual").await {
+        Ok(Some(backup)) => {
+            ctx.say(
+                MessageBuilder::new()
+                    .push("Created backup ")
+                    .push_mono_safe(&backup.name)
+                    .push(".")
+                    .build(),
+            )
+            .await?
+        }
+        Ok(None) => ctx.say("There is no userdata to back up yet.").await?,
+        Err(e) => ctx.say(format!("Failed to back up: {:?}", e)).await?,
+    };
+
+    Ok(())
+}
+
+/// Replace the userdata with a backup. Will restart the server.
+#[poise::command(slash_command, check = "crate::checks::is_admin")]
+pub async fn restore(
+    ctx: Context<'_>,
+    #[description = "Backup to restore"]
+    #[autocomplete = "autocomplete_backup"]
+    name: String,
+    #[description = "Gameserver instance (default the main one)"]
+    #[autocomplete = "crate::commands::autocomplete_instance"]
+    instance: Option<String>,
+) -> Result<(), Error> {
+    let instance = crate::commands::instance(ctx, instance).await?;
+    let backups = instance.server.lock().await.paths().backups.clone();
+
+    let backup = match backup::list(&backups)
+        .await?
+        .into_iter()
+        .find(|backup| backup.name == name)
+    {
+        Some(backup) => backup,
+        None => {
+            ctx.say(
+                MessageBuilder::new()
+                    .push("There is no backup called ")
+                    .push_mono_safe(&name)
+                    .push(". Check `backup list` for the available ones.")
+                    .build(),
+            )
+            .await?;
+            return Ok(());
+        }
+    };
+
+    let consequences = format!(
+        "The world, characters and server configs are reset to <t:{}:F> and the server restarts. \
+        The current userdata is backed up first.{}",
+        backup.created,
+        crate::commands::players_notice(instance).await
+    );
+    if !crate::commands::confirm(ctx, &consequences).await? {
+        return Ok(());
+    }
+
+    let mut server = instance.server.lock().await;
+    let state = instance.state.lock().await;
+
+    server.stop().await;
+
+    let mut response = MessageBuilder::new();
+    // The backup to restore might be the oldest one kept.
+    let restart = match server
+        .backup_sparing(state.cargo_args(), "restore", Some(&backup))
+        .await
+    {
+        Err(e) => {
+            response.push(format!(
+                "Failed to back up userdata, nothing changed: {}",
+                e
+            ));
+            true
+        }
+        Ok(previous) => {
+            let userdata = server.userdata(state.cargo_args());
+            match backup::restore(&userdata, &backup).await {
+                Ok(()) => {
+                    response
+                        .push("Restored ")
+                        .push_mono_safe(&backup.name)
+                        .push(" and restarted the server.");
+                    true
+                }
+                Err(e) => {
+                    log::error!("Failed to restore backup {}: {:?}", backup.name, e);
+                    response.push(format!("Failed to restore backup: {}.", e));
+                    // Don't start the gameserver on half of the userdata.
+                    match previous {
+                        Some(previous) => match backup::restore(&userdata, &previous).await {
+                            Ok(()) => {
+                                response.push(
+                                    " Put the previous userdata back and restarted the server.",
+                                );
+                                true
+                            }
+                            Err(e) => {
+                                log::error!("Failed to put back backup {}: {:?}", previous.name, e);
+                                response
+                                    .push(format!(
+                                        " Failed to put the previous userdata back: {}. It is in ",
+                                        e
+                                    ))
+                                    .push_mono_safe(&previous.name)
+                                    .push(", the server stays stopped.");
+                                false
+                            }
+                        },
+                        None => {
+                            response
+                                .push(" There was no userdata before, the server stays stopped.");
+                            false
+                        }
+                    }
+                }
+            }
+        }
+    };
+
+    if restart {
+        server
+            .start(
+                state.rev(),
+                state.args(),
+                state.cargo_args(),
+                &state.envs(),
+                state.restart_policy(),
+            )
+            .await;
+    }
+
+    ctx.say(response.build()).await?;
+
+    Ok(())
+}
@@ -0,0 +1,87 @@
+use super::players;
+use crate::discord::Context;
+use crate::discord::Error;
+use crate::server::editable::{self, List, Record};
+use chrono::Utc;
+
+/// Manage the players who may not join.
+#[poise::command(
+    slash_command,
+    check = "crate::checks::is_viewer",
+    subcommands("add", "remove", "list")
+)]
+pub async fn ban(_ctx: Context<'_>) -> Result<(), Error> {
+    // Discord doesn't allow root commands to be invoked. Only Subcommands.
+    Ok(())
+}
+
+/// Ban a player. Restarts a running server.
+#[poise::command(slash_command, check = "crate::checks::is_operator")]
+pub async fn add(
+    ctx: Context<'_>,
+    #[description = "Name of the player"] username: String,
+    #[description = "Account uuid of the player"] uuid: String,
+    #[description = "Why the player gets banned, they get to see it"] reason: String,
+    #[description = "Hours until the ban ends (default never)"] hours: Option<u32>,
+    #[description = "Gameserver instance (default the main one)"]
+    #[autocomplete = "crate::commands::autocomplete_instance"]
+    instance: Option<String>,
+) -> Result<(), Error> {
+    let instance = crate::commands::instance(ctx, instance).await?;
+    if !players::is_username(&username) || !players::is_uuid(&uuid) {
+        ctx.say("Specify the name of the player and their uuid like `f81d4fae-7dec-11d0-a765-00a0c91e6bf6`.")
+            .await?;
+        return Ok(());
+    }
+    let uuid = uuid.to_lowercase();
+
+    let now = Utc::now();
+    let until = hours.map(|hours| now + chrono::Duration::hours(hours.into()));
+    let record = Record::Ban { reason, until };
+    let done = match until {
+        Some(until) => format!("Banned `{}` until <t:{}:F>.", username, until.timestamp()),
+        None => format!("Banned `{}`.", username),
+    };
+    // Banning again replaces the ban, the previous one is kept in the history.
+    players::edit(ctx, instance, List::Banlist, &done, |content| {
+        editable::add(content, &uuid, &username, &record, now)
+    })
+    .await
+}
+
+/// Lift the ban of a player, including their history of bans. Restarts a running server.
+#[poise::command(slash_command, check = "crate::checks::is_operator")]
+pub async fn remove(
+    ctx: Context<'_>,
+    #[description = "Name or uuid of the player"] player: String,
+    #[description = "Gameserver instance (default the main one)"]
+    #[autocomplete = "crate::commands::autocomplete_instance"]
+    instance: Option<String>,
+) -> Result<(), Error> {
+    let instance = crate::commands::instance(ctx, instance).await?;
+
+    let done = format!("Lifted the ban of `{}`.", player);
+    players::edit(
+        ctx,
+        instance,
+        List::Banlist,
+        &done,
+        |content| match editable::remove(content, List::Banlist, &player)? {
+            Some((new, _)) => Ok(new),
+            None => Err(format!("`{}` is not banned.", player)),
+        },
+    )
+    .await
+}
+
+/// List the banned players.
+#[poise::command(slash_command, check = "crate::checks::is_viewer")]
+pub async fn list(
+    ctx: Context<'_>,
+    #[description = "Gameserver instance (default the main one)"]
+    #[autocomplete = "crate::commands::autocomplete_instance"]
+    instance: Option<String>,
+) -> Result<(), Error> {
+    let instance = crate::commands::instance(ctx, instance).await?;
+    players::list(ctx, instance, List::Banlist).await
+}
@@ -1,11 +1,12 @@
 use crate::discord::Context;
 use crate::discord::Error;
+use crate::server::Workspace;
 use poise::serenity_prelude::MessageBuilder;
 
 /// Manage arguments passed to cargo.
 #[poise::command(
     slash_command,
-    check = "crate::checks::is_admin",
+    check = "crate::checks::is_viewer",
     subcommands("add", "remove", "list", "reset")
 )]
 pub async fn cargo(_ctx: Context<'_>) -> Result<(), Error> {
@@ -14,13 +15,36 @@ pub async fn cargo(_ctx: Context<'_>) -> Result<(), Error> {
 }
 
 /// Add argument passed to cargo.
-#[poise::command(slash_command, check = "crate::checks::is_admin")]
+#[poise::command(slash_command, check = "crate::checks::is_deployer")]
 pub async fn add(
     ctx: Context<'_>,
-    #[description = "argument to add"] argument: String,
+    #[description = "argument to add, e.g. --release or --features=tracy"] argument: String,
+    #[description = "Gameserver instance (default the main one)"]
+    #[autocomplete = "crate::commands::autocomplete_instance"]
+    instance: Option<String>,
 ) -> Result<(), Error> {
-    let mut state = ctx.data().state.lock().await;
+    let instance = crate::commands::instance(ctx, instance).await?;
+    ctx.defer().await?;
+    let checkout = instance.server.lock().await.paths().home.clone();
 
+    match Workspace::query(&checkout).await {
+        Ok(workspace) => {
+            if let Err(why) = workspace.validate(&argument) {
+                ctx.say(why).await?;
+                return Ok(());
+            }
+        }
+        Err(e) => {
+            ctx.say(format!(
+                "Failed to check `{}` against the workspace.",
+                argument
+            ))
+            .await?;
+            return Err(e.into());
+        }
+    }
+
+    let mut state = instance.state.lock().await;
     state.add_cargo_arg(&argument).await?;
     ctx.say(format!("Added `{}` as cargo argument.", argument))
         .await?;
@@ -29,12 +53,16 @@ pub async fn add(
 }
 
 /// Remove argument passed to cargo.
-#[poise::command(slash_command, check = "crate::checks::is_admin")]
+#[poise::command(slash_command, check = "crate::checks::is_deployer")]
 pub async fn remove(
     ctx: Context<'_>,
     #[description = "argument to remove"] argument: String,
+    #[description = "Gameserver instance (default the main one)"]
+    #[autocomplete = "crate::commands::autocomplete_instance"]
+    instance: Option<String>,
 ) -> Result<(), Error> {
-    let mut state = ctx.data().state.lock().await;
+    let instance = crate::commands::instance(ctx, instance).await?;
+    let mut state = instance.state.lock().await;
 
     state.remove_cargo_arg(&argument).await?;
     ctx.say(format!("Removed `{}` from the cargo arguments.", argument))
@@ -44,9 +72,15 @@ pub async fn remove(
 }
 
 /// List arguments passed to cargo.
-#[poise::command(slash_command, check = "crate::checks::is_admin")]
-pub async fn list(ctx: Context<'_>) -> Result<(), Error> {
-    let state = ctx.data().state.lock().await;
+#[poise::command(slash_command, check = "crate::checks::is_viewer")]
+pub async fn list(
+    ctx: Context<'_>,
+    #[description = "Gameserver instance (default the main one)"]
+    #[autocomplete = "crate::commands::autocomplete_instance"]
+    instance: Option<String>,
+) -> Result<(), Error> {
+    let instance = crate::commands::instance(ctx, instance).await?;
+    let state = instance.state.lock().await;
 
     let mut response = MessageBuilder::new();
     response.push_bold_line("Cargo Arguments:");
@@ -62,9 +96,15 @@ pub async fn list(ctx: Context<'_>) -> Result<(), Error> {
 }
 
 /// Reset arguments passed to cargo to default.
-#[poise::command(slash_command, check = "crate::checks::is_admin")]
-pub async fn reset(ctx: Context<'_>) -> Result<(), Error> {
-    let mut state = ctx.data().state.lock().await;
+#[poise::command(slash_command, check = "crate::checks::is_deployer")]
+pub async fn reset(
+    ctx: Context<'_>,
+    #[description = "Gameserver instance (default the main one)"]
+    #[autocomplete = "crate::commands::autocomplete_instance"]
+    instance: Option<String>,
+) -> Result<(), Error> {
+    let instance = crate::commands::instance(ctx, instance).await?;
+    let mut state = instance.state.lock().await;
 
     state.clear_cargo_args().await?;
     ctx.say("Reset all cargo arguments to default.").await?;
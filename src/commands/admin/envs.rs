@@ -1,12 +1,15 @@
 use crate::discord::Context;
+use crate::discord::Data;
 use crate::discord::Error;
 use poise::serenity_prelude::MessageBuilder;
+use poise::CreateReply;
+use poise::Modal;
 
 /// Manage environment variables passed to the gameserver.
 #[poise::command(
     slash_command,
-    check = "crate::checks::is_admin",
-    subcommands("set", "remove", "list", "reset")
+    check = "crate::checks::is_viewer",
+    subcommands("set", "secret", "remove", "list", "reset")
 )]
 pub async fn envs(_ctx: Context<'_>) -> Result<(), Error> {
     // Discord doesn't allow root commands to be invoked. Only Subcommands.
@@ -14,19 +17,28 @@ pub async fn envs(_ctx: Context<'_>) -> Result<(), Error> {
 }
 
 #[derive(Debug, poise::Modal)]
+#[name = "Secret environment variable"]
 struct EnvVar {
+    #[name = "Name"]
+    #[placeholder = "AUTH_SERVER_KEY"]
     name: String,
+    #[name = "Value"]
+    #[placeholder = "Never shown again"]
     value: String,
 }
 
 /// Set an evironment variable.
-#[poise::command(slash_command, check = "crate::checks::is_admin")]
+#[poise::command(slash_command, check = "crate::checks::is_deployer")]
 pub async fn set(
     ctx: Context<'_>,
     #[description = "Environment Variable name"] name: String,
     #[description = "Environment Variable value"] value: String,
+    #[description = "Gameserver instance (default the main one)"]
+    #[autocomplete = "crate::commands::autocomplete_instance"]
+    instance: Option<String>,
 ) -> Result<(), Error> {
-    let mut state = ctx.data().state.lock().await;
+    let instance = crate::commands::instance(ctx, instance).await?;
+    let mut state = instance.state.lock().await;
     state.add_env(&name, &value).await?;
 
     ctx.say(format!(
@@ -38,13 +50,49 @@ pub async fn set(
     Ok(())
 }
 
+/// Set an environment variable whose value is never shown.
+#[poise::command(slash_command, check = "crate::checks::is_deployer")]
+pub async fn secret(
+    ctx: poise::ApplicationContext<'_, Data, Error>,
+    #[description = "Gameserver instance (default the main one)"]
+    #[autocomplete = "crate::commands::autocomplete_instance"]
+    instance: Option<String>,
+) -> Result<(), Error> {
+    let instance = crate::commands::instance(ctx.into(), instance).await?;
+
+    // Entered in a modal, so the value doesn't show up in the command invocation.
+    let env = match EnvVar::execute(ctx).await? {
+        Some(env) => env,
+        None => return Ok(()),
+    };
+
+    let mut state = instance.state.lock().await;
+    state.add_secret_env(&env.name, &env.value).await?;
+
+    ctx.send(
+        CreateReply::default()
+            .content(format!(
+                "Set `{}` as secret environment variable.",
+                env.name
+            ))
+            .ephemeral(true),
+    )
+    .await?;
+
+    Ok(())
+}
+
 /// Remove an Environment Variable
-#[poise::command(slash_command, check = "crate::checks::is_admin")]
+#[poise::command(slash_command, check = "crate::checks::is_deployer")]
 pub async fn remove(
     ctx: Context<'_>,
     #[description = "Environment Variable value to remove"] name: String,
+    #[description = "Gameserver instance (default the main one)"]
+    #[autocomplete = "crate::commands::autocomplete_instance"]
+    instance: Option<String>,
 ) -> Result<(), Error> {
-    let mut state = ctx.data().state.lock().await;
+    let instance = crate::commands::instance(ctx, instance).await?;
+    let mut state = instance.state.lock().await;
 
     state.remove_env(&name).await?;
     ctx.say(format!(
@@ -57,16 +105,23 @@ pub async fn remove(
 }
 
 /// List all Environment Variables
-#[poise::command(slash_command, check = "crate::checks::is_admin")]
-pub async fn list(ctx: Context<'_>) -> Result<(), Error> {
-    let state = ctx.data().state.lock().await;
+#[poise::command(slash_command, check = "crate::checks::is_viewer")]
+pub async fn list(
+    ctx: Context<'_>,
+    #[description = "Gameserver instance (default the main one)"]
+    #[autocomplete = "crate::commands::autocomplete_instance"]
+    instance: Option<String>,
+) -> Result<(), Error> {
+    let instance = crate::commands::instance(ctx, instance).await?;
+    let state = instance.state.lock().await;
 
     let mut response = MessageBuilder::new();
     response.push_bold_line("Environment variables:");
-    for (env, value) in state.envs() {
+    let envs = state.masked_envs();
+    for (env, value) in &envs {
         response.push_mono_line_safe(format!("{} : {}", env, value));
     }
-    if state.envs().is_empty() {
+    if envs.is_empty() {
         response.push_italic_line("No environment variables set.");
     }
     ctx.say(response.build()).await?;
@@ -74,10 +129,31 @@ pub async fn list(ctx: Context<'_>) -> Result<(), Error> {
 }
 
 /// Reset all Environment Variables to default.
-#[poise::command(slash_command, check = "crate::checks::is_admin")]
-pub async fn reset(ctx: Context<'_>) -> Result<(), Error> {
-    let mut state = ctx.data().state.lock().await;
+#[poise::command(slash_command, check = "crate::checks::is_deployer")]
+pub async fn reset(
+    ctx: Context<'_>,
+    #[description = "Gameserver instance (default the main one)"]
+    #[autocomplete = "crate::commands::autocomplete_instance"]
+    instance: Option<String>,
+) -> Result<(), Error> {
+    let instance = crate::commands::instance(ctx, instance).await?;
+
+    let consequences = {
+        let state = instance.state.lock().await;
+        let envs = state.envs();
+        let secrets = envs.keys().filter(|name| state.is_secret(name)).count();
+        format!(
+            "All {} environment variables, {} of them secret, will be replaced by the defaults. \
+            Applies on the next restart.",
+            envs.len(),
+            secrets
+        )
+    };
+    if !crate::commands::confirm(ctx, &consequences).await? {
+        return Ok(());
+    }
 
+    let mut state = instance.state.lock().await;
     state.reset_envs().await?;
     ctx.say("Reset all environment variables to default.")
         .await?;
@@ -1,10 +1,47 @@
 use crate::discord::Context;
 use crate::discord::Error;
+use crate::utils;
+use poise::serenity_prelude::MessageBuilder;
 
-/// Execute a Veloren cli command.
-#[poise::command(slash_command, hide_in_help)]
-pub async fn exec(ctx: Context<'_>) -> Result<(), Error> {
-    ctx.say("This command is still WIP, sorry!").await?;
+/// Discord refuses messages longer than 2000 characters. Leave some room for formatting.
+const MAX_OUTPUT_LEN: usize = 1900;
+
+/// Execute a Veloren server-cli command.
+#[poise::command(slash_command, check = "crate::checks::is_admin")]
+pub async fn exec(
+    ctx: Context<'_>,
+    #[description = "Command to send to the gameserver"] command: String,
+    #[description = "Gameserver instance (default the main one)"]
+    #[autocomplete = "crate::commands::autocomplete_instance"]
+    instance: Option<String>,
+) -> Result<(), Error> {
+    let instance = crate::commands::instance(ctx, instance).await?;
+    ctx.defer().await?;
+    let response = instance.server.lock().await.exec(&command);
+
+    let lines = match response.await {
+        Some(lines) => lines,
+        None => {
+            ctx.say("Server is not running.").await?;
+            return Ok(());
+        }
+    };
+
+    if lines.is_empty() {
+        ctx.say(format!(
+            "Sent `{}`. The gameserver did not respond.",
+            command
+        ))
+        .await?;
+        return Ok(());
+    }
+
+    ctx.say(
+        MessageBuilder::new()
+            .push_codeblock_safe(utils::tail(&lines, MAX_OUTPUT_LEN), None)
+            .build(),
+    )
+    .await?;
 
     Ok(())
 }
@@ -4,8 +4,10 @@ use anyhow::Context as AnyhowContext;
 use poise::serenity_prelude::Attachment;
 use poise::serenity_prelude::CreateAttachment;
 use poise::serenity_prelude::MessageBuilder;
+use poise::ChoiceParameter;
 use poise::CreateReply;
-use std::path::PathBuf;
+use similar::TextDiff;
+use std::path::{Path, PathBuf};
 use tokio::io::AsyncWriteExt;
 
 #[derive(Debug, poise::ChoiceParameter)]
@@ -19,31 +21,77 @@ pub enum File {
     CliSettings,
 }
 
-// TODO: Do not hardcode files & their path.
 impl File {
-    pub fn path(&self) -> PathBuf {
+    pub fn path(&self, userdata: &Path) -> PathBuf {
         match self {
-            File::Db => PathBuf::from("veloren/target/debug/userdata/server/saves/db.sqlite"),
-            File::Admins => {
-                PathBuf::from("veloren/target/debug/userdata/server/server_config/admins.ron")
-            }
-            File::Banlist => {
-                PathBuf::from("veloren/target/debug/userdata/server/server_config/banlist.ron")
-            }
-            File::Description => {
-                PathBuf::from("veloren/target/debug/userdata/server/server_config/description.ron")
-            }
-            File::Settings => {
-                PathBuf::from("veloren/target/debug/userdata/server/server_config/settings.ron")
-            }
-            File::Whitelist => {
-                PathBuf::from("veloren/target/debug/userdata/server/server_config/whitelist.ron")
-            }
-            File::CliSettings => {
-                PathBuf::from("veloren/target/debug/userdata/server-cli/settings.ron")
+            File::Db => userdata.join("server/saves/db.sqlite"),
+            File::Admins => userdata.join("server/server_config/admins.ron"),
+            File::Banlist => userdata.join("server/server_config/banlist.ron"),
+            File::Description => userdata.join("server/server_config/description.ron"),
+            File::Settings => userdata.join("server/server_config/settings.ron"),
+            File::Whitelist => userdata.join("server/server_config/whitelist.ron"),
+            File::CliSettings => userdata.join("server-cli/settings.ron"),
+        }
+    }
+
+    /// Checks that `content` is RON of the shape the gameserver expects for this file.
+    /// Tells where it is invalid otherwise.
+    fn validate(&self, content: &str) -> Result<(), String> {
+        let value = ron::from_str::<ron::Value>(content).map_err(|e| {
+            let line = content.lines().nth(e.position.line.saturating_sub(1));
+            let mut msg = MessageBuilder::new();
+            msg.push(format!(
+                "Invalid RON at line {}, column {}: {}",
+                e.position.line, e.position.col, e.code
+            ));
+            if let Some(line) = line {
+                let caret = " ".repeat(e.position.col.saturating_sub(1));
+                msg.push_codeblock_safe(format!("{}\n{}^", line, caret), None);
             }
+            msg.build()
+        })?;
+
+        let (valid, expected) = match self {
+            File::Db => return Ok(()),
+            File::Admins | File::Banlist | File::Whitelist => (
+                matches!(&value, ron::Value::Seq(inner) if matches!(inner[..], [ron::Value::Map(_)])),
+                "a versioned map like `V1({ ... })`",
+            ),
+            File::Description => (
+                matches!(&value, ron::Value::Seq(inner) if inner.len() == 1),
+                "a versioned value like `V2({ ... })`",
+            ),
+            File::Settings | File::CliSettings => (
+                matches!(value, ron::Value::Map(_) | ron::Value::Unit),
+                "a struct like `( ... )`",
+            ),
+        };
+        match valid {
+            true => Ok(()),
+            false => Err(format!("Expected {} for {}.", expected, self.name())),
+        }
+    }
+}
+
+/// Unified diff of `old` and `new` to show in a message, shortened to `max_len`.
+fn create_diff_msg(old: &str, new: &str, max_len: usize) -> String {
+    let diff = TextDiff::from_lines(old, new)
+        .unified_diff()
+        .context_radius(2)
+        .to_string();
+
+    let mut shortened = String::new();
+    for line in diff.lines() {
+        if shortened.len() + line.len() + 1 > max_len {
+            shortened.push_str("...\n");
+            break;
         }
+        shortened.push_str(line);
+        shortened.push('\n');
     }
+    MessageBuilder::new()
+        .push_codeblock_safe(shortened, Some("diff"))
+        .build()
 }
 
 /// Manage Veloren server files.
@@ -63,9 +111,12 @@ pub async fn upload(
     ctx: Context<'_>,
     #[description = "which file to upload"] file: File,
     #[description = "which file to upload"] newfile: Attachment,
+    #[description = "Gameserver instance (default the main one)"]
+    #[autocomplete = "crate::commands::autocomplete_instance"]
+    instance: Option<String>,
 ) -> Result<(), Error> {
-    let mut server = ctx.data().server.lock().await;
-    let state = ctx.data().state.lock().await;
+    let instance = crate::commands::instance(ctx, instance).await?;
+    ctx.defer().await?;
 
     // Note: This will download the file straight to RAM.
     let content = match newfile.download().await {
@@ -77,9 +128,68 @@ pub async fn upload(
         }
     };
 
+    if matches!(file, File::Db) {
+        let consequences = format!(
+            "The world, all characters and their items are replaced by {} and the server restarts. \
+            The current userdata is backed up first.{}",
+            newfile.filename,
+            crate::commands::players_notice(instance).await
+        );
+        if !crate::commands::confirm(ctx, &consequences).await? {
+            return Ok(());
+        }
+    } else {
+        let new = match String::from_utf8(content.clone()) {
+            Ok(new) => new,
+            Err(_) => {
+                ctx.say(format!("{} is not a text file.", newfile.filename))
+                    .await?;
+                return Ok(());
+            }
+        };
+        if let Err(e) = file.validate(&new) {
+            ctx.say(format!("Rejected {}. {}", newfile.filename, e))
+                .await?;
+            return Ok(());
+        }
+
+        let path = {
+            let server = instance.server.lock().await;
+            let state = instance.state.lock().await;
+            file.path(&server.userdata(state.cargo_args()))
+        };
+        let old = tokio::fs::read_to_string(&path).await.unwrap_or_default();
+        if old == new {
+            ctx.say(format!("{} is unchanged.", file.name())).await?;
+            return Ok(());
+        }
+        ctx.say(create_diff_msg(&old, &new, 1800)).await?;
+
+        let consequences = format!(
+            "{} will be replaced as shown above and the server restarts.{}",
+            file.name(),
+            crate::commands::players_notice(instance).await
+        );
+        if !crate::commands::confirm(ctx, &consequences).await? {
+            return Ok(());
+        }
+    }
+
+    let mut server = instance.server.lock().await;
+    let state = instance.state.lock().await;
+
+    if let Err(e) = server.backup(state.cargo_args(), "upload").await {
+        ctx.say(format!(
+            "Failed to back up userdata, nothing changed: {}",
+            e
+        ))
+        .await?;
+        return Ok(());
+    }
+
     server.stop().await;
 
-    let mut file = tokio::fs::File::create(file.path())
+    let mut file = tokio::fs::File::create(file.path(&server.userdata(state.cargo_args())))
         .await
         .context("Failed to open file for upload.")?;
     file.write_all(&content)
@@ -90,7 +200,13 @@ pub async fn upload(
         .context("Failed to sync data for upload.")?;
 
     server
-        .start(state.rev(), state.args(), state.cargo_args(), state.envs())
+        .start(
+            state.rev(),
+            state.args(),
+            state.cargo_args(),
+            &state.envs(),
+            state.restart_policy(),
+        )
         .await;
 
     ctx.say("File uploaded and server restarted.").await?;
@@ -103,19 +219,52 @@ pub async fn upload(
 pub async fn remove(
     ctx: Context<'_>,
     #[description = "which file to remove"] file: File,
+    #[description = "Gameserver instance (default the main one)"]
+    #[autocomplete = "crate::commands::autocomplete_instance"]
+    instance: Option<String>,
 ) -> Result<(), Error> {
-    let mut server = ctx.data().server.lock().await;
-    let state = ctx.data().state.lock().await;
+    let instance = crate::commands::instance(ctx, instance).await?;
+
+    let consequences = format!(
+        "{} will be deleted for good and the server restarts.{}{}",
+        file.name(),
+        match file {
+            File::Db => " This wipes the world, all characters and their items.",
+            _ => " The gameserver recreates it with the defaults.",
+        },
+        crate::commands::players_notice(instance).await
+    );
+    if !crate::commands::confirm(ctx, &consequences).await? {
+        return Ok(());
+    }
+
+    let mut server = instance.server.lock().await;
+    let state = instance.state.lock().await;
+
+    if let Err(e) = server.backup(state.cargo_args(), "remove").await {
+        ctx.say(format!(
+            "Failed to back up userdata, nothing changed: {}",
+            e
+        ))
+        .await?;
+        return Ok(());
+    }
 
     server.stop().await;
 
-    if let Err(e) = tokio::fs::remove_file(file.path()).await {
+    if let Err(e) = tokio::fs::remove_file(file.path(&server.userdata(state.cargo_args()))).await {
         ctx.say(format!("Failed to delete file: {}", e)).await?;
         return Ok(());
     }
 
     server
-        .start(state.rev(), state.args(), state.cargo_args(), state.envs())
+        .start(
+            state.rev(),
+            state.args(),
+            state.cargo_args(),
+            &state.envs(),
+            state.restart_policy(),
+        )
         .await;
 
     ctx.say("File removed and server restarted.").await?;
@@ -128,11 +277,19 @@ pub async fn remove(
 pub async fn view(
     ctx: Context<'_>,
     #[description = "which file to view"] file: File,
+    #[description = "Gameserver instance (default the main one)"]
+    #[autocomplete = "crate::commands::autocomplete_instance"]
+    instance: Option<String>,
 ) -> Result<(), Error> {
-    let path = file.path();
+    let instance = crate::commands::instance(ctx, instance).await?;
+    let path = {
+        let server = instance.server.lock().await;
+        let state = instance.state.lock().await;
+        file.path(&server.userdata(state.cargo_args()))
+    };
 
-    if file.path().extension().unwrap() == "ron" {
-        let content = match tokio::fs::read_to_string(file.path()).await {
+    if path.extension().unwrap() == "ron" {
+        let content = match tokio::fs::read_to_string(&path).await {
             Ok(content) => content,
             Err(e) => {
                 ctx.say(format!("Failed to read file: {}", e)).await?;
@@ -159,3 +316,42 @@ pub async fn view(
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn player_lists_are_versioned_maps() {
+        assert!(File::Admins.validate("V1({})").is_ok());
+        assert!(File::Banlist
+            .validate(r#"V1({"f81d4fae-7dec-11d0-a765-00a0c91e6bf6": (username_when_performed: "alice")})"#)
+            .is_ok());
+        assert!(File::Whitelist.validate("{}").is_err());
+        assert!(File::Whitelist.validate("V1([])").is_err());
+        assert!(File::Whitelist.validate("V1({}, {})").is_err());
+    }
+
+    #[test]
+    fn descriptions_are_versioned_values() {
+        assert!(File::Description
+            .validate(r#"V2({"en": (motd: "Hi")})"#)
+            .is_ok());
+        assert!(File::Description.validate(r#""Hi""#).is_err());
+    }
+
+    #[test]
+    fn settings_are_structs() {
+        assert!(File::Settings.validate("(max_players: 100)").is_ok());
+        assert!(File::CliSettings.validate("()").is_ok());
+        assert!(File::Settings.validate("[100]").is_err());
+    }
+
+    #[test]
+    fn invalid_ron_is_located() {
+        let error = File::Settings
+            .validate("(\n    max_players: 100,\n    motd: \"Hi,\n)")
+            .unwrap_err();
+        assert!(error.starts_with("Invalid RON at line "), "{}", error);
+    }
+}
@@ -0,0 +1,146 @@
+use super::players;
+use crate::discord::Context;
+use crate::discord::Error;
+use crate::server::{
+    editable::{self, List, Record},
+    ServerStatus,
+};
+use chrono::Utc;
+
+/// Manage the admins and moderators of the gameserver.
+#[poise::command(
+    slash_command,
+    check = "crate::checks::is_viewer",
+    subcommands("add", "remove", "list")
+)]
+pub async fn gameadmin(_ctx: Context<'_>) -> Result<(), Error> {
+    // Discord doesn't allow root commands to be invoked. Only Subcommands.
+    Ok(())
+}
+
+#[derive(Debug, poise::ChoiceParameter)]
+pub enum AdminRole {
+    Moderator,
+    Admin,
+}
+
+impl From<AdminRole> for editable::AdminRole {
+    fn from(role: AdminRole) -> Self {
+        match role {
+            AdminRole::Moderator => Self::Moderator,
+            AdminRole::Admin => Self::Admin,
+        }
+    }
+}
+
+/// Make a player an admin or moderator, live if the server is online.
+#[poise::command(slash_command, check = "crate::checks::is_admin")]
+pub async fn add(
+    ctx: Context<'_>,
+    #[description = "Name of the player"] username: String,
+    #[description = "Role to give them"] role: AdminRole,
+    #[description = "Account uuid of the player, needed while the server is offline"] uuid: Option<
+        String,
+    >,
+    #[description = "Gameserver instance (default the main one)"]
+    #[autocomplete = "crate::commands::autocomplete_instance"]
+    instance: Option<String>,
+) -> Result<(), Error> {
+    let instance = crate::commands::instance(ctx, instance).await?;
+    if !players::is_username(&username) {
+        ctx.say(format!("`{}` is not the name of a player.", username))
+            .await?;
+        return Ok(());
+    }
+    let role = editable::AdminRole::from(role);
+    ctx.defer().await?;
+
+    // Resolves to `None` if the server isn't online.
+    let response = instance.server.lock().await.add_admin(&username, role);
+    if let Some(response) = response.await {
+        let command = format!("admin add {} {}", username, role.to_string().to_lowercase());
+        return players::say_response(ctx, &command, response).await;
+    }
+
+    let uuid = match uuid.filter(|uuid| players::is_uuid(uuid)) {
+        Some(uuid) => uuid.to_lowercase(),
+        None => {
+            ctx.say(
+                "The server is not online, so the admins have to be edited offline. \
+                Specify the `uuid` of the player for that.",
+            )
+            .await?;
+            return Ok(());
+        }
+    };
+    let done = format!("Made `{}` {}.", username, role);
+    players::edit(ctx, instance, List::Admins, &done, |content| {
+        editable::add(content, &uuid, &username, &Record::Admin(role), Utc::now())
+    })
+    .await
+}
+
+/// Take the role of an admin or moderator, live if the server is online.
+#[poise::command(slash_command, check = "crate::checks::is_admin")]
+pub async fn remove(
+    ctx: Context<'_>,
+    #[description = "Name or uuid of the player"] player: String,
+    #[description = "Gameserver instance (default the main one)"]
+    #[autocomplete = "crate::commands::autocomplete_instance"]
+    instance: Option<String>,
+) -> Result<(), Error> {
+    let instance = crate::commands::instance(ctx, instance).await?;
+    ctx.defer().await?;
+
+    if instance.server.lock().await.status() == ServerStatus::Online {
+        // The console only knows players by name.
+        let username = match players::is_uuid(&player) {
+            true => match players::entries(ctx, instance, List::Admins).await? {
+                Some(entries) => entries
+                    .into_iter()
+                    .find(|entry| entry.uuid.eq_ignore_ascii_case(&player))
+                    .and_then(|entry| entry.username),
+                None => return Ok(()),
+            },
+            false => Some(player.clone()),
+        };
+        let username = match username.filter(|username| players::is_username(username)) {
+            Some(username) => username,
+            None => {
+                ctx.say(format!("There is no admin `{}`.", player)).await?;
+                return Ok(());
+            }
+        };
+
+        let response = instance.server.lock().await.remove_admin(&username);
+        if let Some(response) = response.await {
+            let command = format!("admin remove {}", username);
+            return players::say_response(ctx, &command, response).await;
+        }
+    }
+
+    let done = format!("Removed `{}` from the admins.", player);
+    players::edit(
+        ctx,
+        instance,
+        List::Admins,
+        &done,
+        |content| match editable::remove(content, List::Admins, &player)? {
+            Some((new, _)) => Ok(new),
+            None => Err(format!("`{}` is not an admin.", player)),
+        },
+    )
+    .await
+}
+
+/// List the admins and moderators.
+#[poise::command(slash_command, check = "crate::checks::is_viewer")]
+pub async fn list(
+    ctx: Context<'_>,
+    #[description = "Gameserver instance (default the main one)"]
+    #[autocomplete = "crate::commands::autocomplete_instance"]
+    instance: Option<String>,
+) -> Result<(), Error> {
+    let instance = crate::commands::instance(ctx, instance).await?;
+    players::list(ctx, instance, List::Admins).await
+}
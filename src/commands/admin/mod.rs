@@ -1,28 +1,52 @@
-use poise::serenity_prelude::MessageBuilder;
+use poise::serenity_prelude::{AutocompleteChoice, MessageBuilder};
 use poise::CreateReply;
 
 use crate::discord::Context;
 use crate::discord::Error;
+use crate::server::Snapshot;
+use crate::state::{RestartPolicy, Rev};
 
 pub mod args;
+pub mod audit;
+pub mod backup;
+pub mod ban;
 pub mod cargo;
 pub mod envs;
 pub mod exec;
 pub mod files;
+pub mod gameadmin;
+pub mod players;
+pub mod profile;
+pub mod schedule;
+pub mod whitelist;
 
-/// Switch the revision (Branch/Commit) of the Veloren server. Will restart the server.
-#[poise::command(slash_command, check = "crate::checks::is_admin")]
+/// Switch the revision (Branch/Tag/MR/Commit) of the Veloren server. Will restart the server.
+#[poise::command(slash_command, check = "crate::checks::is_deployer")]
 pub async fn rev(
     ctx: Context<'_>,
-    #[description = "Commit or branch to switch to."] rev: String,
+    #[description = "Branch, tag, merge request (!1234) or commit to switch to."]
+    #[autocomplete = "autocomplete_rev"]
+    rev: String,
+    #[description = "Gameserver instance (default the main one)"]
+    #[autocomplete = "crate::commands::autocomplete_instance"]
+    instance: Option<String>,
 ) -> Result<(), Error> {
-    let mut server = ctx.data().server.lock().await;
+    let instance = crate::commands::instance(ctx, instance).await?;
+    let mut server = instance.server.lock().await;
     let settings = ctx.data().settings.lock().await;
-    let mut state = ctx.data().state.lock().await;
+    let mut state = instance.state.lock().await;
 
     let edit_msg = ctx.say("Checking if rev exists...").await?;
 
-    match state.set_rev(&rev, &settings.repository).await? {
+    match state
+        .set_rev(
+            &rev,
+            &settings.repository,
+            &server.paths().home,
+            Some(ctx.author().id),
+        )
+        .await?
+    {
         true => {
             edit_msg
                 .edit(
@@ -33,9 +57,7 @@ pub async fn rev(
                     )),
                 )
                 .await?;
-            server
-                .restart(state.rev(), state.args(), state.cargo_args(), state.envs())
-                .await;
+            server.deploy(&instance.server, &state).await;
         }
         false => {
             edit_msg
@@ -50,8 +72,84 @@ pub async fn rev(
     Ok(())
 }
 
+/// Discord refuses more autocomplete choices.
+const MAX_CHOICES: usize = 25;
+/// Discord refuses longer autocomplete choice names and values.
+const MAX_CHOICE_LEN: usize = 100;
+
+/// Suggests branches and recent commits matching `partial`.
+async fn autocomplete_rev(ctx: Context<'_>, partial: &str) -> Vec<AutocompleteChoice> {
+    let refs = ctx.data().refs.lock().await;
+    let partial = partial.to_lowercase();
+
+    let branches = refs
+        .branches
+        .iter()
+        .filter(|branch| branch.to_lowercase().contains(&partial))
+        // Can't be shortened, the value has to be the whole name.
+        .filter(|branch| branch.chars().count() <= MAX_CHOICE_LEN)
+        .map(|branch| AutocompleteChoice::new(branch.clone(), branch.clone()));
+    let commits = refs
+        .commits
+        .iter()
+        .filter(|(hash, subject)| {
+            hash.starts_with(&partial) || subject.to_lowercase().contains(&partial)
+        })
+        .map(|(hash, subject)| {
+            let name: String = format!("{} – {}", hash, subject)
+                .chars()
+                .take(MAX_CHOICE_LEN)
+                .collect();
+            AutocompleteChoice::new(name, hash.clone())
+        });
+
+    branches.chain(commits).take(MAX_CHOICES).collect()
+}
+
+/// Redeploy a previous rev which came up online. Will restart the server.
+#[poise::command(slash_command, check = "crate::checks::is_deployer")]
+pub async fn rollback(
+    ctx: Context<'_>,
+    #[description = "How many known-good deployments to go back (default 1)"]
+    #[min = 1]
+    n: Option<usize>,
+    #[description = "Gameserver instance (default the main one)"]
+    #[autocomplete = "crate::commands::autocomplete_instance"]
+    instance: Option<String>,
+) -> Result<(), Error> {
+    let instance = crate::commands::instance(ctx, instance).await?;
+    let mut server = instance.server.lock().await;
+    let mut state = instance.state.lock().await;
+
+    let deployment = match state.known_good(n.unwrap_or(1)) {
+        Some(deployment) => deployment.clone(),
+        None => {
+            ctx.say("No previous deployment to roll back to. Check `history` for what's known.")
+                .await?;
+            return Ok(());
+        }
+    };
+    // Checked by `known_good`.
+    let commit = deployment.commit.unwrap_or_default();
+
+    state
+        .deploy(Rev::Commit(commit.clone()), Some(ctx.author().id))
+        .await?;
+    server.deploy(&instance.server, &state).await;
+
+    let mut msg = MessageBuilder::new();
+    msg.push("Rolling back to ").push_mono_safe(&commit);
+    if !matches!(deployment.rev, Rev::Commit(_)) {
+        msg.push(" of ").push_mono_safe(deployment.rev.to_string());
+    }
+    msg.push(". Check with `status` for servers' progress.");
+    ctx.say(msg.build()).await?;
+
+    Ok(())
+}
+
 /// Sends you the details to aquire the logs.
-#[poise::command(slash_command, ephemeral, check = "crate::checks::is_admin")]
+#[poise::command(slash_command, ephemeral, check = "crate::checks::is_viewer")]
 pub async fn logs(ctx: Context<'_>) -> Result<(), Error> {
     let settings = ctx.data().settings.lock().await;
 
@@ -74,13 +172,25 @@ pub async fn logs(ctx: Context<'_>) -> Result<(), Error> {
 }
 
 /// Start Veloren Server. Will recompile, change branch/commit, fetch updates as needed.
-#[poise::command(slash_command, check = "crate::checks::is_admin")]
-pub async fn start(ctx: Context<'_>) -> Result<(), Error> {
-    let mut server = ctx.data().server.lock().await;
-    let state = ctx.data().state.lock().await;
+#[poise::command(slash_command, check = "crate::checks::is_operator")]
+pub async fn start(
+    ctx: Context<'_>,
+    #[description = "Gameserver instance (default the main one)"]
+    #[autocomplete = "crate::commands::autocomplete_instance"]
+    instance: Option<String>,
+) -> Result<(), Error> {
+    let instance = crate::commands::instance(ctx, instance).await?;
+    let mut server = instance.server.lock().await;
+    let state = instance.state.lock().await;
 
     let resp = match server
-        .start(state.rev(), state.args(), state.cargo_args(), state.envs())
+        .start(
+            state.rev(),
+            state.args(),
+            state.cargo_args(),
+            &state.envs(),
+            state.restart_policy(),
+        )
         .await
     {
         true => "Started Veloren Server. Check with `status` for its progress.",
@@ -93,10 +203,24 @@ pub async fn start(ctx: Context<'_>) -> Result<(), Error> {
 }
 
 /// Stop the Veloren server.
-#[poise::command(slash_command, check = "crate::checks::is_admin")]
-pub async fn stop(ctx: Context<'_>) -> Result<(), Error> {
-    let mut server = ctx.data().server.lock().await;
+#[poise::command(slash_command, check = "crate::checks::is_operator")]
+pub async fn stop(
+    ctx: Context<'_>,
+    #[description = "Gameserver instance (default the main one)"]
+    #[autocomplete = "crate::commands::autocomplete_instance"]
+    instance: Option<String>,
+) -> Result<(), Error> {
+    let instance = crate::commands::instance(ctx, instance).await?;
 
+    let consequences = format!(
+        "The server will shut down and stay offline until started again.{}",
+        crate::commands::players_notice(instance).await
+    );
+    if !crate::commands::confirm(ctx, &consequences).await? {
+        return Ok(());
+    }
+
+    let mut server = instance.server.lock().await;
     let resp = match server.stop().await {
         true => "Stopped the Veloren Server.",
         false => "Server is already stopped.",
@@ -108,13 +232,34 @@ pub async fn stop(ctx: Context<'_>) -> Result<(), Error> {
 }
 
 /// Runs cargo clean and restarts the server.
-#[poise::command(slash_command, check = "crate::checks::is_admin")]
-pub async fn prune(ctx: Context<'_>) -> Result<(), Error> {
-    let mut server = ctx.data().server.lock().await;
-    let state = ctx.data().state.lock().await;
+#[poise::command(slash_command, check = "crate::checks::is_operator")]
+pub async fn prune(
+    ctx: Context<'_>,
+    #[description = "Gameserver instance (default the main one)"]
+    #[autocomplete = "crate::commands::autocomplete_instance"]
+    instance: Option<String>,
+) -> Result<(), Error> {
+    let instance = crate::commands::instance(ctx, instance).await?;
 
+    let consequences = format!(
+        "All build artifacts will be deleted and the server restarts, \
+        recompiling everything from scratch. This takes a long time.{}",
+        crate::commands::players_notice(instance).await
+    );
+    if !crate::commands::confirm(ctx, &consequences).await? {
+        return Ok(());
+    }
+
+    let mut server = instance.server.lock().await;
+    let state = instance.state.lock().await;
     match server
-        .clean(state.rev(), state.args(), state.cargo_args(), state.envs())
+        .clean(
+            state.rev(),
+            state.args(),
+            state.cargo_args(),
+            &state.envs(),
+            state.restart_policy(),
+        )
         .await
     {
         true => {
@@ -130,13 +275,26 @@ pub async fn prune(ctx: Context<'_>) -> Result<(), Error> {
 }
 
 /// Restart Veloren Server. Will recompile, change branch/commit, fetch updates as needed.
-#[poise::command(slash_command, check = "crate::checks::is_admin")]
-pub async fn restart(ctx: Context<'_>) -> Result<(), Error> {
-    let mut server = ctx.data().server.lock().await;
-    let state = ctx.data().state.lock().await;
+#[poise::command(slash_command, check = "crate::checks::is_operator")]
+pub async fn restart(
+    ctx: Context<'_>,
+    #[description = "Gameserver instance (default the main one)"]
+    #[autocomplete = "crate::commands::autocomplete_instance"]
+    instance: Option<String>,
+) -> Result<(), Error> {
+    let instance = crate::commands::instance(ctx, instance).await?;
+    ctx.defer().await?;
+    let mut server = instance.server.lock().await;
+    let state = instance.state.lock().await;
 
     server
-        .restart(state.rev(), state.args(), state.cargo_args(), state.envs())
+        .restart(
+            state.rev(),
+            state.args(),
+            state.cargo_args(),
+            &state.envs(),
+            state.restart_policy(),
+        )
         .await;
 
     ctx.say("Restarted Veloren Server. Check with `status` for its progress.")
@@ -144,3 +302,114 @@ pub async fn restart(ctx: Context<'_>) -> Result<(), Error> {
 
     Ok(())
 }
+
+/// Restart the server if the rev, arguments or envs changed since it has been started.
+#[poise::command(slash_command, check = "crate::checks::is_operator")]
+pub async fn apply(
+    ctx: Context<'_>,
+    #[description = "Gameserver instance (default the main one)"]
+    #[autocomplete = "crate::commands::autocomplete_instance"]
+    instance: Option<String>,
+) -> Result<(), Error> {
+    let instance = crate::commands::instance(ctx, instance).await?;
+    ctx.defer().await?;
+    let mut server = instance.server.lock().await;
+    let state = instance.state.lock().await;
+
+    let resp = match server.started_with() {
+        None => "Server is not running. Use `start` to start it.",
+        Some(running) if *running == Snapshot::of(&state) => {
+            "Nothing changed since the server has been started."
+        }
+        Some(_) => {
+            server.deploy(&instance.server, &state).await;
+            "Applying changes. Check with `status` for servers' progress."
+        }
+    };
+
+    ctx.say(resp).await?;
+
+    Ok(())
+}
+
+#[derive(Debug, poise::ChoiceParameter)]
+pub enum Policy {
+    Never,
+    OnFailure,
+    Always,
+}
+
+/// Set what happens when the Veloren server exits on its own. Applies on the next (re)start.
+#[poise::command(slash_command, check = "crate::checks::is_operator")]
+pub async fn restart_policy(
+    ctx: Context<'_>,
+    #[description = "When to restart the server"] policy: Policy,
+    #[description = "Restarts in a row on failure (default 3)"] max_retries: Option<u32>,
+    #[description = "Gameserver instance (default the main one)"]
+    #[autocomplete = "crate::commands::autocomplete_instance"]
+    instance: Option<String>,
+) -> Result<(), Error> {
+    let instance = crate::commands::instance(ctx, instance).await?;
+    let mut state = instance.state.lock().await;
+
+    let policy = match policy {
+        Policy::Never => RestartPolicy::Never,
+        Policy::OnFailure => RestartPolicy::OnFailure {
+            max_retries: max_retries.unwrap_or(3),
+        },
+        Policy::Always => RestartPolicy::Always,
+    };
+    state.set_restart_policy(policy).await?;
+
+    ctx.say(format!("Restart policy set to `{}`.", policy))
+        .await?;
+
+    Ok(())
+}
+
+/// Build new revs while the current one keeps running and only switch once the build succeeded.
+#[poise::command(slash_command, check = "crate::checks::is_deployer")]
+pub async fn seamless(
+    ctx: Context<'_>,
+    #[description = "Keep the server online while switching revs"] enabled: bool,
+    #[description = "Gameserver instance (default the main one)"]
+    #[autocomplete = "crate::commands::autocomplete_instance"]
+    instance: Option<String>,
+) -> Result<(), Error> {
+    let instance = crate::commands::instance(ctx, instance).await?;
+    let mut state = instance.state.lock().await;
+
+    state.set_seamless(enabled).await?;
+
+    ctx.say(match enabled {
+        true => "Switching revs keeps the server online until the new rev is built.",
+        false => "Switching revs restarts the server right away.",
+    })
+    .await?;
+
+    Ok(())
+}
+
+/// Redeploy the branch whenever it gets new commits, once no players are online.
+#[poise::command(slash_command, check = "crate::checks::is_deployer")]
+pub async fn follow(
+    ctx: Context<'_>,
+    #[description = "Follow new commits of the branch"] enabled: bool,
+    #[description = "Gameserver instance (default the main one)"]
+    #[autocomplete = "crate::commands::autocomplete_instance"]
+    instance: Option<String>,
+) -> Result<(), Error> {
+    let instance = crate::commands::instance(ctx, instance).await?;
+    let mut state = instance.state.lock().await;
+
+    state.set_follow(enabled).await?;
+
+    ctx.say(match (enabled, state.rev()) {
+        (true, Rev::Branch(_)) => "Following new commits of the branch.",
+        (true, _) => "Will follow new commits once a branch is deployed.",
+        (false, _) => "Stopped following new commits.",
+    })
+    .await?;
+
+    Ok(())
+}
@@ -0,0 +1,218 @@
+use super::files::File;
+use crate::discord::{Context, Error, Instance};
+use crate::server::editable::{self, List};
+use crate::utils;
+use anyhow::Context as AnyhowContext;
+use poise::serenity_prelude::MessageBuilder;
+use poise::ChoiceParameter;
+use std::path::{Path, PathBuf};
+
+/// Discord refuses messages longer than 2000 characters. Leave some room for formatting.
+const MAX_MSG_LEN: usize = 1900;
+
+fn file(list: List) -> File {
+    match list {
+        List::Admins => File::Admins,
+        List::Banlist => File::Banlist,
+        List::Whitelist => File::Whitelist,
+    }
+}
+
+/// Whether `uuid` looks like `f81d4fae-7dec-11d0-a765-00a0c91e6bf6`.
+pub fn is_uuid(uuid: &str) -> bool {
+    uuid.len() == 36
+        && uuid.char_indices().all(|(i, c)| match i {
+            8 | 13 | 18 | 23 => c == '-',
+            _ => c.is_ascii_hexdigit(),
+        })
+}
+
+/// Whether `username` can be the name of a Veloren account.
+/// Also keeps it from passing further arguments to console commands.
+pub fn is_username(username: &str) -> bool {
+    !username.is_empty()
+        && username.len() <= 32
+        && username
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+async fn path(instance: &Instance, list: List) -> PathBuf {
+    let server = instance.server.lock().await;
+    let state = instance.state.lock().await;
+    file(list).path(&server.userdata(state.cargo_args()))
+}
+
+/// Content of the file at `path`, `None` if there is none yet.
+async fn read(path: &Path) -> Result<Option<String>, Error> {
+    match tokio::fs::read_to_string(path).await {
+        Ok(content) => Ok(Some(content)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// The players on `list` of `instance`, `None` if the user has been told why there are none.
+pub async fn entries(
+    ctx: Context<'_>,
+    instance: &Instance,
+    list: List,
+) -> Result<Option<Vec<editable::Entry>>, Error> {
+    let content = read(&path(instance, list).await).await?;
+    match editable::entries(content.as_deref(), list) {
+        Ok(entries) => Ok(Some(entries)),
+        Err(e) => {
+            ctx.say(format!("Failed to read {}: {}", file(list).name(), e))
+                .await?;
+            Ok(None)
+        }
+    }
+}
+
+/// Posts the players on `list` of `instance`.
+pub async fn list(ctx: Context<'_>, instance: &Instance, list: List) -> Result<(), Error> {
+    let entries = match entries(ctx, instance, list).await? {
+        Some(entries) => entries,
+        None => return Ok(()),
+    };
+
+    let mut response = MessageBuilder::new();
+    response
+        .push(format!("{} of ", file(list).name()))
+        .push_bold_safe(&instance.name)
+        .push_line(":");
+    let lines = entries.iter().map(|entry| {
+        let mut line = MessageBuilder::new();
+        line.push_mono_safe(&entry.uuid);
+        if let Some(username) = &entry.username {
+            line.push(" ").push_bold_safe(username);
+        }
+        if let Some(detail) = &entry.detail {
+            line.push(" ").push_safe(detail);
+        }
+        line.build()
+    });
+    let mut len = 0;
+    for (i, line) in lines.enumerate() {
+        len += line.len() + 1;
+        if len > MAX_MSG_LEN {
+            response.push_italic_line(format!("and {} more.", entries.len() - i));
+            break;
+        }
+        response.push_line(line);
+    }
+    if entries.is_empty() {
+        response.push_italic_line("Nobody.");
+    }
+    ctx.say(response.build()).await?;
+
+    Ok(())
+}
+
+/// Changes `list` of `instance` by writing what `edit` makes of the current content of its file,
+/// which is `None` if there is none yet. `edit` tells why it can't be changed otherwise.
+///
+/// The gameserver only reads the file on start, so a running one gets restarted after confirmation.
+pub async fn edit(
+    ctx: Context<'_>,
+    instance: &Instance,
+    list: List,
+    done: &str,
+    edit: impl Fn(Option<&str>) -> Result<String, String>,
+) -> Result<(), Error> {
+    // Don't ask for confirmation of something which doesn't work out anyway.
+    if let Err(e) = edit(read(&path(instance, list).await).await?.as_deref()) {
+        ctx.say(format!("{} Nothing changed.", e)).await?;
+        return Ok(());
+    }
+
+    let running = instance.server.lock().await.started_with().is_some();
+    if running {
+        let consequences = format!(
+            "The server restarts to apply the change, it only reads {} on start.{}",
+            file(list).name(),
+            crate::commands::players_notice(instance).await
+        );
+        if !crate::commands::confirm(ctx, &consequences).await? {
+            return Ok(());
+        }
+    }
+
+    let mut server = instance.server.lock().await;
+    let state = instance.state.lock().await;
+
+    // Might have changed while waiting for the confirmation.
+    let path = file(list).path(&server.userdata(state.cargo_args()));
+    let new = match edit(read(&path).await?.as_deref()) {
+        Ok(new) => new,
+        Err(e) => {
+            ctx.say(format!("{} Nothing changed.", e)).await?;
+            return Ok(());
+        }
+    };
+
+    let reason = file(list).name().to_lowercase();
+    if let Err(e) = server.backup(state.cargo_args(), &reason).await {
+        ctx.say(format!(
+            "Failed to back up userdata, nothing changed: {}",
+            e
+        ))
+        .await?;
+        return Ok(());
+    }
+
+    let was_running = server.stop().await;
+
+    if let Some(dir) = path.parent() {
+        tokio::fs::create_dir_all(dir)
+            .await
+            .context("Failed to create server config dir.")?;
+    }
+    let written = tokio::fs::write(&path, new)
+        .await
+        .with_context(|| format!("Failed to write {}.", path.display()));
+
+    if was_running {
+        server
+            .start(
+                state.rev(),
+                state.args(),
+                state.cargo_args(),
+                &state.envs(),
+                state.restart_policy(),
+            )
+            .await;
+    }
+    written?;
+
+    ctx.say(format!(
+        "{} {}",
+        done,
+        match was_running {
+            true => "Restarted the server to apply it.",
+            false => "It applies when the server starts.",
+        }
+    ))
+    .await?;
+
+    Ok(())
+}
+
+/// Posts the `response` of the gameserver to `command`.
+pub async fn say_response(
+    ctx: Context<'_>,
+    command: &str,
+    response: Vec<String>,
+) -> Result<(), Error> {
+    let mut msg = MessageBuilder::new();
+    msg.push("Sent ").push_mono_safe(command);
+    if response.is_empty() {
+        msg.push(". The gameserver did not respond.");
+    } else {
+        msg.push_line(":")
+            .push_codeblock_safe(utils::tail(&response, MAX_MSG_LEN), None);
+    }
+    ctx.say(msg.build()).await?;
+
+    Ok(())
+}
@@ -0,0 +1,182 @@
+use crate::discord::{Context, Error};
+use crate::server::Workspace;
+use crate::state::Profile;
+use linked_hash_set::LinkedHashSet;
+use poise::serenity_prelude::MessageBuilder;
+use std::{collections::BTreeSet, path::Path};
+
+/// Manage bundles of cargo arguments, environment variables and gameserver arguments.
+#[poise::command(
+    slash_command,
+    check = "crate::checks::is_viewer",
+    subcommands("use_", "save", "list")
+)]
+pub async fn profile(_ctx: Context<'_>) -> Result<(), Error> {
+    // Discord doesn't allow root commands to be invoked. Only Subcommands.
+    Ok(())
+}
+
+/// Suggests the profiles of all instances matching `partial`.
+async fn autocomplete_profile(ctx: Context<'_>, partial: &str) -> Vec<String> {
+    let mut names = BTreeSet::new();
+    for instance in ctx.data().instances.values() {
+        names.extend(instance.state.lock().await.profiles().into_keys());
+    }
+
+    names
+        .into_iter()
+        .filter(|name| name.starts_with(partial))
+        .collect()
+}
+
+/// Checks the `cargo` arguments against the workspace in `checkout`.
+/// Returns whether they are valid, the user has been told why otherwise.
+async fn validate(
+    ctx: Context<'_>,
+    checkout: &Path,
+    cargo: &LinkedHashSet<String>,
+) -> Result<bool, Error> {
+    let workspace = match Workspace::query(checkout).await {
+        Ok(workspace) => workspace,
+        Err(e) => {
+            ctx.say("Failed to check the cargo arguments against the workspace.")
+                .await?;
+            return Err(e.into());
+        }
+    };
+    for argument in cargo {
+        if let Err(why) = workspace.validate(argument) {
+            ctx.say(why).await?;
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// Switch to a profile. Will restart the server.
+#[poise::command(slash_command, rename = "use", check = "crate::checks::is_deployer")]
+pub async fn use_(
+    ctx: Context<'_>,
+    #[description = "Profile to use"]
+    #[autocomplete = "autocomplete_profile"]
+    name: String,
+    #[description = "Gameserver instance (default the main one)"]
+    #[autocomplete = "crate::commands::autocomplete_instance"]
+    instance: Option<String>,
+) -> Result<(), Error> {
+    let instance = crate::commands::instance(ctx, instance).await?;
+    ctx.defer().await?;
+    let mut server = instance.server.lock().await;
+    let mut state = instance.state.lock().await;
+
+    let profile = match state.profiles().remove(&name) {
+        Some(profile) => profile,
+        None => {
+            ctx.say(format!(
+                "There is no profile called `{}`. Check `profile list` for the available ones.",
+                name
+            ))
+            .await?;
+            return Ok(());
+        }
+    };
+    if !validate(ctx, &server.paths().home, &profile.cargo).await? {
+        return Ok(());
+    }
+    state.use_profile(profile).await?;
+
+    server
+        .restart(
+            state.rev(),
+            state.args(),
+            state.cargo_args(),
+            &state.envs(),
+            state.restart_policy(),
+        )
+        .await;
+
+    ctx.say(format!(
+        "Switched to profile `{}` and restarted the server. Check with `status` for its progress.",
+        name
+    ))
+    .await?;
+
+    Ok(())
+}
+
+/// Save the current cargo arguments, environment variables and gameserver arguments.
+#[poise::command(slash_command, check = "crate::checks::is_deployer")]
+pub async fn save(
+    ctx: Context<'_>,
+    #[description = "Name to save the profile as"] name: String,
+    #[description = "Gameserver instance (default the main one)"]
+    #[autocomplete = "crate::commands::autocomplete_instance"]
+    instance: Option<String>,
+) -> Result<(), Error> {
+    let instance = crate::commands::instance(ctx, instance).await?;
+
+    if Profile::presets().contains_key(&name) {
+        ctx.say(format!(
+            "`{}` is a preset and can't be overwritten. Pick another name.",
+            name
+        ))
+        .await?;
+        return Ok(());
+    }
+
+    ctx.defer().await?;
+    let server = instance.server.lock().await;
+    let mut state = instance.state.lock().await;
+    if !validate(ctx, &server.paths().home, state.cargo_args()).await? {
+        return Ok(());
+    }
+    state.save_profile(&name).await?;
+    ctx.say(format!("Saved the current settings as profile `{}`.", name))
+        .await?;
+
+    Ok(())
+}
+
+/// List presets and saved profiles.
+#[poise::command(slash_command, check = "crate::checks::is_viewer")]
+pub async fn list(
+    ctx: Context<'_>,
+    #[description = "Gameserver instance (default the main one)"]
+    #[autocomplete = "crate::commands::autocomplete_instance"]
+    instance: Option<String>,
+) -> Result<(), Error> {
+    let instance = crate::commands::instance(ctx, instance).await?;
+    let state = instance.state.lock().await;
+
+    let presets = Profile::presets();
+    let current = state.current_profile();
+
+    let mut response = MessageBuilder::new();
+    response.push_bold_line("Profiles:");
+    for (name, profile) in state.profiles() {
+        response.push_mono_safe(&name);
+        if presets.contains_key(&name) {
+            response.push(" (preset)");
+        }
+        if profile == current {
+            response.push(" :white_check_mark: in use");
+        }
+        response.push_line("");
+
+        for (label, list) in [("cargo", &profile.cargo), ("args", &profile.args)].iter() {
+            response.push(format!("{}: ", label));
+            match list.is_empty() {
+                true => response.push_italic("none"),
+                false => {
+                    let list: Vec<_> = list.iter().map(String::as_str).collect();
+                    response.push_mono_safe(list.join(" "))
+                }
+            };
+            response.push(", ");
+        }
+        response.push_line(format!("{} envs", profile.envs.len()));
+    }
+    ctx.say(response.build()).await?;
+
+    Ok(())
+}
@@ -0,0 +1,188 @@
+use crate::discord::Context;
+use crate::discord::Error;
+use crate::state::{Action, Repeat, State};
+use chrono::{Datelike, NaiveTime, Utc};
+use poise::serenity_prelude::MessageBuilder;
+
+/// Manage actions which run at certain times.
+#[poise::command(
+    slash_command,
+    check = "crate::checks::is_viewer",
+    subcommands("add", "list", "remove")
+)]
+pub async fn schedule(_ctx: Context<'_>) -> Result<(), Error> {
+    // Discord doesn't allow root commands to be invoked. Only Subcommands.
+    Ok(())
+}
+
+#[derive(Debug, poise::ChoiceParameter)]
+pub enum Task {
+    Restart,
+    Prune,
+    Rev,
+}
+
+#[derive(Debug, poise::ChoiceParameter)]
+pub enum Interval {
+    Once,
+    Daily,
+    Weekly,
+}
+
+#[derive(Debug, poise::ChoiceParameter)]
+pub enum Weekday {
+    Monday,
+    Tuesday,
+    Wednesday,
+    Thursday,
+    Friday,
+    Saturday,
+    Sunday,
+}
+
+impl From<Weekday> for chrono::Weekday {
+    fn from(day: Weekday) -> Self {
+        match day {
+            Weekday::Monday => Self::Mon,
+            Weekday::Tuesday => Self::Tue,
+            Weekday::Wednesday => Self::Wed,
+            Weekday::Thursday => Self::Thu,
+            Weekday::Friday => Self::Fri,
+            Weekday::Saturday => Self::Sat,
+            Weekday::Sunday => Self::Sun,
+        }
+    }
+}
+
+/// Schedule an action. Players get warned before it runs.
+#[poise::command(slash_command, check = "crate::checks::is_deployer")]
+pub async fn add(
+    ctx: Context<'_>,
+    #[description = "What to do"] task: Task,
+    #[description = "Time of day in UTC, e.g. 04:00"] time: String,
+    #[description = "How often to do it"] interval: Interval,
+    #[description = "Day of the week (default next occurrence)"] day: Option<Weekday>,
+    #[description = "Rev to switch to"]
+    #[autocomplete = "super::autocomplete_rev"]
+    rev: Option<String>,
+    #[description = "Gameserver instance (default the main one)"]
+    #[autocomplete = "crate::commands::autocomplete_instance"]
+    instance: Option<String>,
+) -> Result<(), Error> {
+    let instance = crate::commands::instance(ctx, instance).await?;
+
+    let time = match NaiveTime::parse_from_str(&time, "%H:%M") {
+        Ok(time) => time,
+        Err(_) => {
+            ctx.say(format!("`{}` is not a time like `04:00`.", time))
+                .await?;
+            return Ok(());
+        }
+    };
+    let action = match (task, rev) {
+        (Task::Restart, _) => Action::Restart,
+        (Task::Prune, _) => Action::Prune,
+        (Task::Rev, Some(rev)) => Action::Rev(rev),
+        (Task::Rev, None) => {
+            ctx.say("Specify the `rev` to switch to.").await?;
+            return Ok(());
+        }
+    };
+    if let Action::Rev(rev) = &action {
+        // Typos would only show when the job runs.
+        ctx.defer().await?;
+        let checkout = instance.server.lock().await.paths().home.clone();
+        let repository = ctx.data().settings.lock().await.repository.clone();
+        if State::resolve_rev(rev, &repository, &checkout)
+            .await?
+            .is_none()
+        {
+            ctx.say(format!("`{}` does not exist!", rev)).await?;
+            return Ok(());
+        }
+    }
+    let repeat = match interval {
+        Interval::Once => Repeat::Once,
+        Interval::Daily => Repeat::Daily,
+        Interval::Weekly => Repeat::Weekly,
+    };
+
+    // Next occurrence of the time on the day.
+    let day = day.map(chrono::Weekday::from);
+    let now = Utc::now();
+    let mut next = now.date_naive().and_time(time).and_utc();
+    while next <= now || day.is_some_and(|day| next.weekday() != day) {
+        next += chrono::Duration::days(1);
+    }
+
+    let id = instance
+        .state
+        .lock()
+        .await
+        .add_job(action.clone(), repeat, next.timestamp())
+        .await?;
+
+    ctx.say(
+        MessageBuilder::new()
+            .push(format!("Scheduled #{} to ", id))
+            .push_bold_safe(action.to_string())
+            .push(format!(" {}, next <t:{}:F>.", repeat, next.timestamp()))
+            .build(),
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// List all scheduled actions.
+#[poise::command(slash_command, check = "crate::checks::is_viewer")]
+pub async fn list(
+    ctx: Context<'_>,
+    #[description = "Gameserver instance (default the main one)"]
+    #[autocomplete = "crate::commands::autocomplete_instance"]
+    instance: Option<String>,
+) -> Result<(), Error> {
+    let instance = crate::commands::instance(ctx, instance).await?;
+    let state = instance.state.lock().await;
+
+    let mut response = MessageBuilder::new();
+    response.push_bold_line("Scheduled actions:");
+    for job in state.schedule() {
+        response
+            .push(format!("#{} ", job.id))
+            .push_bold_safe(job.action.to_string())
+            .push_line(format!(
+                " {}, next <t:{}:F> (<t:{}:R>)",
+                job.repeat, job.next, job.next
+            ));
+    }
+    if state.schedule().is_empty() {
+        response.push_italic_line("Nothing scheduled.");
+    }
+    ctx.say(response.build()).await?;
+
+    Ok(())
+}
+
+/// Remove a scheduled action.
+#[poise::command(slash_command, check = "crate::checks::is_deployer")]
+pub async fn remove(
+    ctx: Context<'_>,
+    #[description = "Number of the scheduled action"] id: u32,
+    #[description = "Gameserver instance (default the main one)"]
+    #[autocomplete = "crate::commands::autocomplete_instance"]
+    instance: Option<String>,
+) -> Result<(), Error> {
+    let instance = crate::commands::instance(ctx, instance).await?;
+    let mut state = instance.state.lock().await;
+
+    match state.remove_job(id).await? {
+        true => {
+            ctx.say(format!("Removed #{} from the schedule.", id))
+                .await?
+        }
+        false => ctx.say(format!("#{} is not scheduled.", id)).await?,
+    };
+
+    Ok(())
+}
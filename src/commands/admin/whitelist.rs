@@ -0,0 +1,85 @@
+use super::players;
+use crate::discord::Context;
+use crate::discord::Error;
+use crate::server::editable::{self, List, Record};
+use chrono::Utc;
+
+/// Manage the players who may join while the whitelist is enabled.
+#[poise::command(
+    slash_command,
+    check = "crate::checks::is_viewer",
+    subcommands("add", "remove", "list")
+)]
+pub async fn whitelist(_ctx: Context<'_>) -> Result<(), Error> {
+    // Discord doesn't allow root commands to be invoked. Only Subcommands.
+    Ok(())
+}
+
+/// Allow a player to join. Restarts a running server.
+#[poise::command(slash_command, check = "crate::checks::is_operator")]
+pub async fn add(
+    ctx: Context<'_>,
+    #[description = "Name of the player"] username: String,
+    #[description = "Account uuid of the player"] uuid: String,
+    #[description = "Gameserver instance (default the main one)"]
+    #[autocomplete = "crate::commands::autocomplete_instance"]
+    instance: Option<String>,
+) -> Result<(), Error> {
+    let instance = crate::commands::instance(ctx, instance).await?;
+    if !players::is_username(&username) || !players::is_uuid(&uuid) {
+        ctx.say("Specify the name of the player and their uuid like `f81d4fae-7dec-11d0-a765-00a0c91e6bf6`.")
+            .await?;
+        return Ok(());
+    }
+    let uuid = uuid.to_lowercase();
+
+    let done = format!("Whitelisted `{}`.", username);
+    players::edit(ctx, instance, List::Whitelist, &done, |content| {
+        let entries = editable::entries(content, List::Whitelist)?;
+        if entries
+            .iter()
+            .any(|entry| entry.uuid.eq_ignore_ascii_case(&uuid))
+        {
+            return Err(format!("`{}` is whitelisted already.", username));
+        }
+        editable::add(content, &uuid, &username, &Record::Whitelist, Utc::now())
+    })
+    .await
+}
+
+/// Disallow a player to join. Restarts a running server.
+#[poise::command(slash_command, check = "crate::checks::is_operator")]
+pub async fn remove(
+    ctx: Context<'_>,
+    #[description = "Name or uuid of the player"] player: String,
+    #[description = "Gameserver instance (default the main one)"]
+    #[autocomplete = "crate::commands::autocomplete_instance"]
+    instance: Option<String>,
+) -> Result<(), Error> {
+    let instance = crate::commands::instance(ctx, instance).await?;
+
+    let done = format!("Removed `{}` from the whitelist.", player);
+    players::edit(
+        ctx,
+        instance,
+        List::Whitelist,
+        &done,
+        |content| match editable::remove(content, List::Whitelist, &player)? {
+            Some((new, _)) => Ok(new),
+            None => Err(format!("`{}` is not whitelisted.", player)),
+        },
+    )
+    .await
+}
+
+/// List the whitelisted players.
+#[poise::command(slash_command, check = "crate::checks::is_viewer")]
+pub async fn list(
+    ctx: Context<'_>,
+    #[description = "Gameserver instance (default the main one)"]
+    #[autocomplete = "crate::commands::autocomplete_instance"]
+    instance: Option<String>,
+) -> Result<(), Error> {
+    let instance = crate::commands::instance(ctx, instance).await?;
+    players::list(ctx, instance, List::Whitelist).await
+}
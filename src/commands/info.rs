@@ -1,15 +1,26 @@
 use crate::discord::Context;
 use crate::discord::Error;
-use crate::{server::ServerStatus, state::Rev};
+use crate::{
+    server::{Diagnostic, Progress, ServerStatus, Snapshot},
+    state::{BuildStats, Deployment, Rev},
+};
 use linked_hash_set::LinkedHashSet;
 use poise::serenity_prelude as serenity;
+use poise::serenity_prelude::CreateAttachment;
 use poise::serenity_prelude::CreateEmbed;
+use poise::serenity_prelude::CreateEmbedFooter;
 use poise::serenity_prelude::MessageBuilder;
 use poise::serenity_prelude::UserId;
 use poise::CreateReply;
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
 
 const VERSION: &str = env!("CARGO_PKG_VERSION");
+/// How many build errors are shown at most.
+const MAX_DIAGNOSTICS: usize = 5;
+/// Discord refuses embed fields longer than 1024 characters. Leave some room for formatting.
+const MAX_FIELD_LEN: usize = 1000;
+/// Width of the build progress bar in characters.
+const PROGRESS_BAR_WIDTH: usize = 20;
 
 /// Explains what this bot is about.
 #[poise::command(slash_command)]
@@ -37,23 +48,85 @@ pub async fn about(ctx: Context<'_>) -> Result<(), Error> {
 }
 
 /// Prints current status of the Veloren Server.
-#[poise::command(slash_command)]
-pub async fn status(ctx: Context<'_>) -> Result<(), Error> {
-    let mut server = ctx.data().server.lock().await;
-    let settings = ctx.data().settings.lock().await;
-    let state = ctx.data().state.lock().await;
+#[poise::command(slash_command, check = "crate::checks::is_viewer")]
+pub async fn status(
+    ctx: Context<'_>,
+    #[description = "Gameserver instance (default the main one)"]
+    #[autocomplete = "crate::commands::autocomplete_instance"]
+    instance: Option<String>,
+) -> Result<(), Error> {
+    let instance = crate::commands::instance(ctx, instance).await?;
+    let server = instance.server.lock().await;
+    let state = instance.state.lock().await;
+
+    let status = server.status();
+    let staging = server.staging();
+    let staging_status = staging.as_ref().and_then(|(_, status)| status.as_ref());
+    let progress = match (&status, staging_status, server.progress()) {
+        (ServerStatus::Compiling, _, Some(progress))
+        | (_, Some(ServerStatus::Compiling), Some(progress)) => {
+            Some(create_progress_msg(&progress, state.builds()))
+        }
+        _ => None,
+    };
+    let failed = status == ServerStatus::CompileFailed;
+    let staging = staging.map(|(rev, status)| {
+        MessageBuilder::new()
+            .push_mono(rev.to_string())
+            .push(": ")
+            .push(match status {
+                Some(status) => status.to_string(),
+                None => "Starting".to_string(),
+            })
+            .build()
+    });
 
-    let status = server.status().await;
+    let pending = server
+        .started_with()
+        .and_then(|running| create_diff_msg(running, &Snapshot::of(&state)));
 
-    ctx.send(CreateReply::default().embed(create_status_msg(
+    let status_msg = create_status_msg(
         &status,
         server.version(),
         state.rev(),
-        &settings.gameserver_address,
-        Some(state.envs().clone()),
+        server.crashes(),
+        server.exit_code(),
+        staging,
+        progress,
+        failed.then(|| server.diagnostics()).as_deref(),
+        pending,
+        &instance.address,
+        Some(state.masked_envs()),
         Some(state.args().clone()),
         Some(state.cargo_args().clone()),
-    )))
+    );
+    let mut reply =
+        CreateReply::default().embed(status_msg.footer(CreateEmbedFooter::new(&instance.name)));
+    if failed {
+        if let Ok(log) = CreateAttachment::path(&server.paths().build_log).await {
+            reply = reply.attachment(log);
+        }
+    }
+    ctx.send(reply).await?;
+
+    Ok(())
+}
+
+/// Lists the recently deployed revs of the Veloren Server.
+#[poise::command(slash_command)]
+pub async fn history(
+    ctx: Context<'_>,
+    #[description = "Gameserver instance (default the main one)"]
+    #[autocomplete = "crate::commands::autocomplete_instance"]
+    instance: Option<String>,
+) -> Result<(), Error> {
+    let instance = crate::commands::instance(ctx, instance).await?;
+    let state = instance.state.lock().await;
+
+    let history_msg = create_history_msg(state.history().iter().rev());
+    ctx.send(
+        CreateReply::default().embed(history_msg.footer(CreateEmbedFooter::new(&instance.name))),
+    )
     .await?;
 
     Ok(())
@@ -64,6 +137,12 @@ fn create_status_msg(
     status: &ServerStatus,
     version: Option<String>,
     rev: &Rev,
+    crashes: u32,
+    exit_code: Option<i32>,
+    staging: Option<String>,
+    progress: Option<String>,
+    diagnostics: Option<&[Diagnostic]>,
+    pending: Option<String>,
     address: &str,
     envs: Option<HashMap<String, String>>,
     args: Option<LinkedHashSet<String>>,
@@ -119,7 +198,14 @@ fn create_status_msg(
     );
 
     match rev {
-        Rev::Branch(branch) => {
+        Rev::Commit(commit) => {
+            e = e.field(
+                "Commit",
+                MessageBuilder::new().push_mono(commit).build(),
+                false,
+            );
+        }
+        rev => {
             if let Some(version) = version {
                 e = e.field(
                     "Commit",
@@ -128,20 +214,43 @@ fn create_status_msg(
                 );
             }
             e = e.field(
-                "Branch",
-                MessageBuilder::new().push_mono(branch).build(),
-                false,
-            );
-        }
-        Rev::Commit(commit) => {
-            e = e.field(
-                "Commit",
-                MessageBuilder::new().push_mono(commit).build(),
+                rev.kind(),
+                MessageBuilder::new().push_mono(rev.to_string()).build(),
                 false,
             );
         }
     }
 
+    if crashes > 0 {
+        e = e.field(
+            "Crashes",
+            MessageBuilder::new().push_mono(crashes.to_string()).build(),
+            true,
+        );
+    }
+    if let Some(exit_code) = exit_code {
+        e = e.field(
+            "Last exit code",
+            MessageBuilder::new()
+                .push_mono(exit_code.to_string())
+                .build(),
+            true,
+        );
+    }
+
+    if let Some(pending) = pending {
+        e = e.field(":arrows_counterclockwise: Restart pending", pending, false);
+    }
+    if let Some(staging) = staging {
+        e = e.field(":construction: Next rev", staging, false);
+    }
+    if let Some(progress) = progress {
+        e = e.field(":hammer: Build progress", progress, false);
+    }
+    if let Some(diagnostics) = diagnostics {
+        e = e.field(":x: Errors", create_diagnostics_msg(diagnostics), false);
+    }
+
     if let Some(envs_msg) = envs_msg {
         e = e.field(":label: Environment variables", envs_msg, false);
     }
@@ -162,3 +271,161 @@ fn create_status_msg(
 
     e
 }
+
+/// Lists what changed in `saved` since the gameserver has been started with `running`.
+/// Returns `None` if nothing changed. Env values are left out, as they might be secret.
+fn create_diff_msg(running: &Snapshot, saved: &Snapshot) -> Option<String> {
+    if running == saved {
+        return None;
+    }
+
+    let mut lines = Vec::new();
+    if running.rev != saved.rev {
+        lines.push(format!("- rev {}", running.rev));
+        lines.push(format!("+ rev {}", saved.rev));
+    }
+    for (label, running, saved) in [
+        ("cargo", &running.profile.cargo, &saved.profile.cargo),
+        ("arg", &running.profile.args, &saved.profile.args),
+    ]
+    .iter()
+    {
+        lines.extend(
+            running
+                .iter()
+                .filter(|arg| !saved.contains(*arg))
+                .map(|arg| format!("- {} {}", label, arg)),
+        );
+        lines.extend(
+            saved
+                .iter()
+                .filter(|arg| !running.contains(*arg))
+                .map(|arg| format!("+ {} {}", label, arg)),
+        );
+    }
+    let (running, saved) = (&running.profile.envs, &saved.profile.envs);
+    let names: BTreeSet<_> = running.keys().chain(saved.keys()).collect();
+    for name in names {
+        match (running.get(name), saved.get(name)) {
+            (Some(running), Some(saved)) if running != saved => {
+                lines.push(format!("! env {}", name))
+            }
+            (Some(_), None) => lines.push(format!("- env {}", name)),
+            (None, Some(_)) => lines.push(format!("+ env {}", name)),
+            _ => {}
+        }
+    }
+    if lines.is_empty() {
+        lines.push("  order of arguments".to_string());
+    }
+
+    // Keep what fits into the field.
+    let mut diff = String::new();
+    for line in lines {
+        if diff.len() + line.len() + 1 > MAX_FIELD_LEN {
+            diff.push_str("...\n");
+            break;
+        }
+        diff.push_str(&line);
+        diff.push('\n');
+    }
+
+    Some(
+        MessageBuilder::new()
+            .push_codeblock_safe(diff, Some("diff"))
+            .build(),
+    )
+}
+
+/// Lists deployments in the given order, with the commit they resolved to and who deployed them.
+fn create_history_msg<'a>(deployments: impl Iterator<Item = &'a Deployment>) -> CreateEmbed {
+    let mut msg = MessageBuilder::new();
+    let mut empty = true;
+    for deployment in deployments {
+        empty = false;
+        msg.push(match deployment.online {
+            true => ":white_check_mark: ",
+            false => ":warning: ",
+        })
+        .push_mono_safe(deployment.rev.to_string());
+        if let Some(commit) = &deployment.commit {
+            match deployment.rev {
+                Rev::Commit(_) => &mut msg,
+                _ => msg.push(" @ ").push_mono_safe(commit),
+            };
+        }
+        if let Some(id) = deployment.deployed_by {
+            msg.push(" by ").mention(&UserId::new(id));
+        }
+        msg.push_line(format!(" <t:{}:R>", deployment.deployed_at));
+    }
+    if empty {
+        msg.push_italic_line("Nothing has been deployed yet.");
+    }
+
+    CreateEmbed::new()
+        .title(":scroll: Veloren Server History")
+        .description(msg.build())
+}
+
+/// Lists the first few build errors.
+pub fn create_diagnostics_msg(diagnostics: &[Diagnostic]) -> String {
+    let mut msg = MessageBuilder::new();
+    if diagnostics.is_empty() {
+        msg.push_italic_line("No errors captured. Check the build log.");
+    }
+
+    let mut len = 0;
+    for diagnostic in diagnostics.iter().take(MAX_DIAGNOSTICS) {
+        let line = diagnostic.to_string();
+        let line = line.lines().next().unwrap_or_default();
+        len += line.len() + 1;
+        if len > MAX_FIELD_LEN {
+            break;
+        }
+        msg.push_mono_line_safe(line);
+    }
+    if diagnostics.len() > MAX_DIAGNOSTICS {
+        msg.push_italic_line(format!(
+            "... and {} more.",
+            diagnostics.len() - MAX_DIAGNOSTICS
+        ));
+    }
+
+    msg.build()
+}
+
+/// Shows how many crates have been compiled and estimates the time left based on previous builds.
+fn create_progress_msg(progress: &Progress, builds: &BuildStats) -> String {
+    let mut msg = MessageBuilder::new();
+
+    match builds.units {
+        Some(total) if total > 0 => {
+            let ratio = (progress.units as f64 / total as f64).min(1.0);
+            let filled = (ratio * PROGRESS_BAR_WIDTH as f64).round() as usize;
+            msg.push_mono(format!(
+                "{}{} {:>3}%",
+                "█".repeat(filled),
+                "░".repeat(PROGRESS_BAR_WIDTH - filled),
+                (ratio * 100.0).round()
+            ))
+            .push_line(format!(" compiled {}/{} crates", progress.units, total));
+        }
+        _ => {
+            msg.push_line(format!("compiled {} crates", progress.units));
+        }
+    }
+
+    if let Some(average) = builds.average_duration() {
+        let elapsed = progress.started.elapsed();
+        match average.checked_sub(elapsed) {
+            Some(left) if left.as_secs() >= 60 => {
+                msg.push_italic_line(format!("About {} minutes left.", left.as_secs() / 60))
+            }
+            Some(_) => msg.push_italic_line("Less than a minute left."),
+            None => msg.push_italic_line("Taking longer than usual."),
+        };
+    }
+
+    msg.build()
+}
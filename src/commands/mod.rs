@@ -2,3 +2,110 @@ pub mod admin;
 pub mod help;
 pub mod info;
 pub mod owner;
+
+use crate::discord::{Context, Error, Instance};
+use poise::serenity_prelude::{
+    ButtonStyle, ComponentInteractionCollector, CreateActionRow, CreateButton,
+    CreateInteractionResponse, MessageBuilder,
+};
+use poise::CreateReply;
+use std::time::Duration;
+
+/// How long the user has to confirm a destructive command.
+const CONFIRM_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Looks up the gameserver instance called `name`, the default one if there is no name.
+/// Tells the user if there is no such instance.
+pub async fn instance<'a>(ctx: Context<'a>, name: Option<String>) -> Result<&'a Instance, Error> {
+    let data = ctx.data();
+    let name = name.unwrap_or_else(|| data.default.clone());
+
+    match data.instances.get(&name) {
+        Some(instance) => Ok(instance),
+        None => {
+            ctx.say(format!("There is no instance called `{}`.", name))
+                .await?;
+            Err(format!("Unknown instance {}", name).into())
+        }
+    }
+}
+
+/// Suggests the names of gameserver instances matching `partial`.
+pub async fn autocomplete_instance(ctx: Context<'_>, partial: &str) -> Vec<String> {
+    ctx.data()
+        .instances
+        .keys()
+        .filter(|name| name.starts_with(partial))
+        .cloned()
+        .collect()
+}
+
+/// Asks the user to confirm an action with the given `consequences` through buttons.
+/// Returns whether they confirmed in time.
+pub async fn confirm(ctx: Context<'_>, consequences: &str) -> Result<bool, Error> {
+    let confirm_id = format!("{}-confirm", ctx.id());
+    let cancel_id = format!("{}-cancel", ctx.id());
+
+    let question = MessageBuilder::new()
+        .push_line(format!(":warning: {}", consequences))
+        .push_bold("Are you sure?")
+        .build();
+    let buttons = CreateActionRow::Buttons(vec![
+        CreateButton::new(&confirm_id)
+            .label("Confirm")
+            .style(ButtonStyle::Danger),
+        CreateButton::new(&cancel_id)
+            .label("Cancel")
+            .style(ButtonStyle::Secondary),
+    ]);
+    let reply = ctx
+        .send(
+            CreateReply::default()
+                .content(&question)
+                .components(vec![buttons]),
+        )
+        .await?;
+
+    let ids = [confirm_id.clone(), cancel_id];
+    let interaction = ComponentInteractionCollector::new(ctx)
+        .author_id(ctx.author().id)
+        .channel_id(ctx.channel_id())
+        .timeout(CONFIRM_TIMEOUT)
+        .filter(move |interaction| ids.contains(&interaction.data.custom_id))
+        .await;
+
+    let confirmed = match &interaction {
+        Some(interaction) => {
+            interaction
+                .create_response(ctx, CreateInteractionResponse::Acknowledge)
+                .await?;
+            interaction.data.custom_id == confirm_id
+        }
+        None => false,
+    };
+    let answer = match (confirmed, interaction.is_some()) {
+        (true, _) => "Confirmed.",
+        (false, true) => "Cancelled, nothing happened.",
+        (false, false) => "Not confirmed in time, nothing happened.",
+    };
+    reply
+        .edit(
+            ctx,
+            CreateReply::default()
+                .content(format!("{}\n{}", question, answer))
+                .components(Vec::new()),
+        )
+        .await?;
+
+    Ok(confirmed)
+}
+
+/// Tells how many players are online on `instance`, to spell out who is affected by an action.
+pub async fn players_notice(instance: &Instance) -> String {
+    let players = instance.server.lock().await.players_online();
+    match players.await {
+        Some(1) => " 1 player is online.".to_string(),
+        Some(players) => format!(" {} players are online.", players),
+        None => String::new(),
+    }
+}
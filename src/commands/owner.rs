@@ -1,9 +1,16 @@
 use crate::discord::Context;
 use crate::discord::Error;
+use crate::state::{Grantee, Role};
 use anyhow::Result;
+use poise::serenity_prelude as serenity;
+use poise::serenity_prelude::CreateAllowedMentions;
+use poise::serenity_prelude::Mentionable;
 use poise::serenity_prelude::MessageBuilder;
 use poise::serenity_prelude::OnlineStatus;
+use poise::serenity_prelude::RoleId;
 use poise::serenity_prelude::User;
+use poise::serenity_prelude::UserId;
+use poise::CreateReply;
 
 /// Shutdown the bot.
 #[poise::command(slash_command, check = "crate::checks::is_owner")]
@@ -16,62 +23,136 @@ pub async fn quit(ctx: Context<'_>) -> Result<(), Error> {
     Ok(())
 }
 
-/// Manage admins which are able to modify the server.
+#[derive(Debug, poise::ChoiceParameter)]
+pub enum Permission {
+    Viewer,
+    Operator,
+    Deployer,
+    Admin,
+}
+
+impl From<Permission> for Role {
+    fn from(permission: Permission) -> Self {
+        match permission {
+            Permission::Viewer => Self::Viewer,
+            Permission::Operator => Self::Operator,
+            Permission::Deployer => Self::Deployer,
+            Permission::Admin => Self::Admin,
+        }
+    }
+}
+
+/// Manage who is allowed to do what, on all instances alike.
 #[poise::command(
     slash_command,
     check = "crate::checks::is_admin",
-    subcommands("add", "remove", "list")
+    subcommands("grant", "revoke", "list")
 )]
-pub async fn admin(_ctx: Context<'_>) -> Result<(), Error> {
+pub async fn role(_ctx: Context<'_>) -> Result<(), Error> {
     // Discord doesn't allow root commands to be invoked. Only Subcommands.
     Ok(())
 }
 
-/// Manage admins which are able to modify the server.
+/// Picks the one of `user` and `discord_role` which has been given. Tells the user otherwise.
+async fn grantee(
+    ctx: Context<'_>,
+    user: Option<User>,
+    discord_role: Option<serenity::Role>,
+) -> Result<Option<Grantee>, Error> {
+    match (user, discord_role) {
+        (Some(user), None) => Ok(Some(Grantee::User(user.id.get()))),
+        (None, Some(role)) => Ok(Some(Grantee::Role(role.id.get()))),
+        _ => {
+            ctx.say("Specify either a `user` or a `discord_role`.")
+                .await?;
+            Ok(None)
+        }
+    }
+}
+
+/// Mentions `grantee` without pinging it.
+fn mention(grantee: Grantee) -> String {
+    match grantee {
+        Grantee::User(id) => UserId::new(id).mention().to_string(),
+        Grantee::Role(id) => RoleId::new(id).mention().to_string(),
+    }
+}
+
+/// Grant a role to a user or everyone with a Discord role. Replaces the role they had.
 #[poise::command(slash_command, check = "crate::checks::is_admin")]
-pub async fn add(
+pub async fn grant(
     ctx: Context<'_>,
-    #[description = "User to add to the admin list"] user: User,
+    #[description = "Role to grant, each includes the ones before"] role: Permission,
+    #[description = "User to grant it to"] user: Option<User>,
+    #[description = "Discord role to grant it to"] discord_role: Option<serenity::Role>,
 ) -> Result<(), Error> {
-    let mut state = ctx.data().state.lock().await;
+    let grantee = match grantee(ctx, user, discord_role).await? {
+        Some(grantee) => grantee,
+        None => return Ok(()),
+    };
+    let role = Role::from(role);
+    let mut state = ctx.data().main().state.lock().await;
 
-    state.add_admin(user.id.get()).await?;
-    ctx.say(format!("Added '{}' to the admins list.", user.tag()))
-        .await?;
+    state.grant(grantee, role).await?;
+    ctx.send(
+        CreateReply::default()
+            .content(format!("Granted `{}` to {}.", role, mention(grantee)))
+            .allowed_mentions(CreateAllowedMentions::new()),
+    )
+    .await?;
 
     Ok(())
 }
 
-/// Manage admins which are able to modify the server.
+/// Take the role of a user or Discord role away.
 #[poise::command(slash_command, check = "crate::checks::is_admin")]
-pub async fn remove(
+pub async fn revoke(
     ctx: Context<'_>,
-    #[description = "User to remove from the admin list"] user: User,
+    #[description = "User to take the role from"] user: Option<User>,
+    #[description = "Discord role to take the role from"] discord_role: Option<serenity::Role>,
 ) -> Result<(), Error> {
-    let mut state = ctx.data().state.lock().await;
+    let grantee = match grantee(ctx, user, discord_role).await? {
+        Some(grantee) => grantee,
+        None => return Ok(()),
+    };
+    let mut state = ctx.data().main().state.lock().await;
 
-    state.remove_admin(user.id.get()).await?;
-    ctx.say(format!("Removed '{}' from the admins list.", user.tag()))
-        .await?;
+    let resp = match state.revoke(grantee).await? {
+        true => format!("Revoked the role of {}.", mention(grantee)),
+        false => format!("{} has no role.", mention(grantee)),
+    };
+    ctx.send(
+        CreateReply::default()
+            .content(resp)
+            .allowed_mentions(CreateAllowedMentions::new()),
+    )
+    .await?;
 
     Ok(())
 }
 
-/// Manage admins which are able to modify the server.
+/// List who has which role.
 #[poise::command(slash_command, check = "crate::checks::is_admin")]
 pub async fn list(ctx: Context<'_>) -> Result<(), Error> {
-    let state = ctx.data().state.lock().await;
+    let state = ctx.data().main().state.lock().await;
 
     let mut response = MessageBuilder::new();
-    response.push_bold_line("Admins:");
-    for admin in state.admins() {
-        let admin = admin.to_user(&ctx.serenity_context().http).await?;
-        response.push_line_safe(format!("{} ({})", admin.tag(), admin.id));
+    response.push_bold_line("Roles:");
+    for grant in state.grants() {
+        response
+            .push(mention(grant.grantee))
+            .push(": ")
+            .push_mono_line(grant.role.to_string());
     }
-    if state.admins().is_empty() {
-        response.push_italic_line("No Admins found.");
+    if state.grants().is_empty() {
+        response.push_italic_line("No roles granted.");
     }
-    ctx.say(response.build()).await?;
+    ctx.send(
+        CreateReply::default()
+            .content(response.build())
+            .allowed_mentions(CreateAllowedMentions::new()),
+    )
+    .await?;
 
     Ok(())
 }
@@ -1,5 +1,17 @@
-use crate::{commands::*, server::Server, settings::Settings, state::State, Result};
-use poise::serenity_prelude::{self as serenity, ActivityData, CacheHttp, OnlineStatus};
+use crate::{
+    audit::{self, Audit, Outcome},
+    backup,
+    commands::*,
+    follow, notifications,
+    refs::{self, Refs},
+    scheduler,
+    server::Server,
+    settings::Settings,
+    state::State,
+    tracker, Result,
+};
+use poise::serenity_prelude::{self as serenity, ActivityData, CacheHttp, ChannelId, OnlineStatus};
+use std::{collections::BTreeMap, sync::Arc, time::Duration};
 use tokio::sync::Mutex;
 
 pub type Error = Box<dyn std::error::Error + Send + Sync>;
@@ -7,8 +19,28 @@ pub type Context<'a> = poise::Context<'a, Data, Error>;
 
 pub struct Data {
     pub settings: Mutex<Settings>,
-    pub state: Mutex<State>,
-    pub server: Mutex<Server>,
+    /// Gameservers by name.
+    pub instances: BTreeMap<String, Instance>,
+    /// Name of the gameserver commands use by default. Its state holds the roles.
+    pub default: String,
+    pub refs: Arc<Mutex<Refs>>,
+    pub audit: Audit,
+}
+
+/// A gameserver and everything the bot keeps track of about it.
+pub struct Instance {
+    pub name: String,
+    /// Gameservers's address.
+    pub address: String,
+    pub state: Arc<Mutex<State>>,
+    pub server: Arc<Mutex<Server>>,
+}
+
+impl Data {
+    /// The gameserver commands use by default.
+    pub fn main(&self) -> &Instance {
+        &self.instances[&self.default]
+    }
 }
 
 async fn event_handler(
@@ -36,52 +68,127 @@ async fn event_handler(
     Ok(())
 }
 
-pub async fn run(settings: Settings, server: Server) -> Result<()> {
+pub async fn run(settings: Settings, servers: BTreeMap<String, Server>) -> Result<()> {
     let token = settings.token.clone();
 
     let options = poise::FrameworkOptions {
         commands: vec![
             info::about(),
             info::status(),
+            info::history(),
             help::help(),
             owner::quit(),
-            owner::admin(),
+            owner::role(),
             admin::rev(),
+            admin::rollback(),
             admin::logs(),
             admin::start(),
             admin::stop(),
             admin::prune(),
             admin::restart(),
+            admin::apply(),
+            admin::restart_policy(),
+            admin::seamless(),
+            admin::follow(),
             admin::exec::exec(),
             admin::args::args(),
             admin::cargo::cargo(),
             admin::envs::envs(),
             admin::files::files(),
+            admin::profile::profile(),
+            admin::schedule::schedule(),
+            admin::audit::audit(),
+            admin::backup::backup(),
+            admin::whitelist::whitelist(),
+            admin::ban::ban(),
+            admin::gameadmin::gameadmin(),
         ],
         event_handler: |ctx, event, framework, user_data| {
             Box::pin(event_handler(ctx, event, framework, user_data))
         },
         on_error: |error| Box::pin(on_error(error)),
         pre_command: |ctx| Box::pin(pre_command(ctx)),
+        post_command: |ctx| Box::pin(Audit::record(ctx, Outcome::Success)),
         ..Default::default()
     };
 
-    let state = match State::new() {
-        Ok(state) => state,
-        Err(_) => {
-            let state = State::default();
-            state.save().await?;
-            state
-        }
-    };
+    let gameservers = settings.gameservers()?;
+    let mut states = BTreeMap::new();
+    for name in servers.keys() {
+        let instance = (*name != settings.gameserver_name).then_some(name.as_str());
+        states.insert(name.clone(), State::open(instance).await?);
+    }
 
     let framework = poise::Framework::builder()
-        .setup(move |_ctx, _ready, _framework| {
+        .setup(move |ctx, _ready, _framework| {
+            let channel = settings.notification_channel.map(ChannelId::new);
+            let refs = Arc::new(Mutex::new(Refs::default()));
+            tokio::spawn(refs::run(
+                refs.clone(),
+                settings.repository.clone(),
+                servers[&settings.gameserver_name].paths().home.clone(),
+            ));
+            let mut instances = BTreeMap::new();
+            for ((name, server), (_, state)) in servers.into_iter().zip(states) {
+                let state = Arc::new(Mutex::new(state));
+                if let Some(channel) = channel {
+                    tokio::spawn(notifications::run(
+                        ctx.http.clone(),
+                        channel,
+                        name.clone(),
+                        server.paths().build_log.clone(),
+                        server.subscribe(),
+                    ));
+                }
+                tokio::spawn(tracker::run(state.clone(), server.subscribe()));
+                let server = Arc::new(Mutex::new(server));
+                tokio::spawn(follow::run(
+                    ctx.http.clone(),
+                    channel,
+                    name.clone(),
+                    state.clone(),
+                    server.clone(),
+                    settings.repository.clone(),
+                    Duration::from_secs(settings.follow_interval),
+                ));
+                tokio::spawn(scheduler::run(
+                    ctx.http.clone(),
+                    channel,
+                    name.clone(),
+                    state.clone(),
+                    server.clone(),
+                    settings.repository.clone(),
+                ));
+                if settings.backup_interval > 0 {
+                    tokio::spawn(backup::run(
+                        state.clone(),
+                        server.clone(),
+                        Duration::from_secs(settings.backup_interval * 60 * 60),
+                    ));
+                }
+
+                let address = gameservers
+                    .get(&name)
+                    .map(|gameserver| gameserver.address.clone())
+                    .unwrap_or_default();
+                instances.insert(
+                    name.clone(),
+                    Instance {
+                        name,
+                        address,
+                        state,
+                        server,
+                    },
+                );
+            }
+
             Box::pin(async move {
                 Ok(Data {
+                    audit: Audit::new(settings.audit_channel.map(ChannelId::new)),
+                    default: settings.gameserver_name.clone(),
                     settings: Mutex::new(settings),
-                    state: Mutex::new(state),
-                    server: Mutex::new(server),
+                    instances,
+                    refs,
                 })
             })
         })
@@ -101,6 +208,7 @@ async fn on_error(error: poise::FrameworkError<'_, Data, Error>) {
     // This is our custom error handler
     // They are many errors that can occur, so we only handle the ones we want to customize
     // and forward the rest to the default handler
+    audit::on_error(&error).await;
     match error {
         poise::FrameworkError::Setup { error, .. } => panic!("Failed to start bot: {:?}", error),
         poise::FrameworkError::Command { error, ctx, .. } => {
@@ -120,4 +228,5 @@ async fn pre_command(ctx: Context<'_>) {
         ctx.command().name,
         ctx.author().tag()
     );
+    Audit::before(ctx).await;
 }
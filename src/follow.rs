@@ -0,0 +1,249 @@
+use crate::{
+    server::{Server, ServerStatus},
+    state::{Rev, State},
+    utils,
+};
+use poise::serenity_prelude::{ChannelId, Http, MessageBuilder};
+use std::{path::Path, sync::Arc, time::Duration};
+use tokio::{
+    process::Command,
+    sync::{broadcast::error::RecvError, Mutex},
+};
+
+/// How many new commits are listed at most.
+const MAX_COMMITS: usize = 10;
+/// Length commit hashes are shortened to.
+const SHORT_HASH_LEN: usize = 10;
+
+/// Redeploys the branch in `state` whenever it gets new commits and follow mode is enabled.
+/// Waits until no players are online and announces if the new commits fail to build.
+pub async fn run(
+    http: Arc<Http>,
+    channel: Option<ChannelId>,
+    name: String,
+    state: Arc<Mutex<State>>,
+    server: Arc<Mutex<Server>>,
+    repository: String,
+    interval: Duration,
+) {
+    let (checkout, mut transitions) = {
+        let server = server.lock().await;
+        (server.paths().home.clone(), server.subscribe())
+    };
+    let mut interval = tokio::time::interval(interval);
+    // Head the players have been waited for already, to only announce it once.
+    let mut waiting = None;
+    // Last head deployed, to not rebuild it over and over if the build failed.
+    let mut deployed = None;
+    // Branch and head which are being built, to announce if that fails.
+    let mut building: Option<(String, String)> = None;
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {}
+            transition = transitions.recv() => {
+                let (to, rev) = match transition {
+                    Ok(transition) => (transition.to, transition.rev),
+                    // Missed transitions can't be told apart, the next ones will do.
+                    Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => return,
+                };
+                let followed = matches!(
+                    (&building, &rev),
+                    (Some((branch, _)), Some(Rev::Branch(rev))) if branch == rev
+                );
+                match to.unstaged() {
+                    ServerStatus::UpdateFailed | ServerStatus::CompileFailed if followed => {
+                        if let Some((branch, head)) = building.take() {
+                            log::warn!("Failed to build {} of followed branch {}.", head, branch);
+                            announce(
+                                &http,
+                                channel,
+                                &name,
+                                MessageBuilder::new()
+                                    .push(":x: Failed to build ")
+                                    .push_mono_safe(&branch)
+                                    .push(" at ")
+                                    .push_mono_safe(&head[..SHORT_HASH_LEN.min(head.len())])
+                                    .push(". Following it again once it gets new commits.")
+                                    .build(),
+                            )
+                            .await;
+                        }
+                    }
+                    // Built and started.
+                    ServerStatus::Online if to == *to.unstaged() => {
+                        building = None;
+                    }
+                    _ => {}
+                }
+                continue;
+            }
+        }
+
+        let branch = {
+            let state = state.lock().await;
+            match state.rev() {
+                Rev::Branch(branch) if state.follow() => branch.clone(),
+                _ => continue,
+            }
+        };
+        let head = match query_head(&checkout, &repository, &branch).await {
+            Ok(Some(head)) if deployed.as_ref() != Some(&head) => head,
+            Ok(_) => continue,
+            Err(e) => {
+                log::warn!("Failed to query head of {}: {}", branch, e);
+                continue;
+            }
+        };
+
+        let version = {
+            let server = server.lock().await;
+            if !can_redeploy(&server) {
+                continue;
+            }
+            match server.version() {
+                Some(version) if head.starts_with(&version) => continue,
+                version => version,
+            }
+        };
+
+        let players = server.lock().await.players_online();
+        let players = players.await;
+        if let Some(players) = players.filter(|players| *players > 0) {
+            if waiting.as_ref() != Some(&head) {
+                log::info!(
+                    "{} has new commits, waiting for {} players.",
+                    branch,
+                    players
+                );
+                announce(
+                    &http,
+                    channel,
+                    &name,
+                    MessageBuilder::new()
+                        .push(":hourglass: ")
+                        .push_mono_safe(&branch)
+                        .push(format!(
+                            " has new commits. Waiting for {} players to leave before updating.",
+                            players
+                        ))
+                        .build(),
+                )
+                .await;
+                waiting = Some(head);
+            }
+            continue;
+        }
+        waiting = None;
+
+        let commits = match new_commits(&checkout, &branch, version.as_deref(), &head).await {
+            Ok(commits) => commits,
+            Err(e) => {
+                log::warn!("Failed to list new commits of {}: {}", branch, e);
+                Vec::new()
+            }
+        };
+        let mut msg = MessageBuilder::new();
+        msg.push(":arrows_counterclockwise: Updating ")
+            .push_mono_safe(&branch)
+            .push(" to ")
+            .push_mono_safe(&head[..SHORT_HASH_LEN.min(head.len())]);
+        if let Some(version) = &version {
+            msg.push(" from ").push_mono_safe(version);
+        }
+        msg.push_line(":");
+        for commit in &commits {
+            msg.push_mono_line_safe(commit);
+        }
+        log::info!("{} has new commits, redeploying.", branch);
+        announce(&http, channel, &name, msg.build()).await;
+
+        let mut server_guard = server.lock().await;
+        let mut state = state.lock().await;
+        // Someone else might have been quicker.
+        if !can_redeploy(&server_guard) || *state.rev() != Rev::Branch(branch.clone()) {
+            continue;
+        }
+        if let Err(e) = state.deploy(Rev::Branch(branch.clone()), None).await {
+            log::error!("Failed to deploy followed branch: {}", e);
+            continue;
+        }
+        server_guard.deploy(&server, &state).await;
+        building = Some((branch, head.clone()));
+        deployed = Some(head);
+    }
+}
+
+/// Whether `server` can be redeployed. Not while it is busy or has been stopped on purpose,
+/// but after a failed build or run, which a new head might fix.
+/// A failed staging is forgotten, so that only skips builds in progress.
+fn can_redeploy(server: &Server) -> bool {
+    let settled = matches!(
+        server.status(),
+        ServerStatus::Online
+            | ServerStatus::UpdateFailed
+            | ServerStatus::CompileFailed
+            | ServerStatus::RunFailed
+    );
+    settled && server.staging().is_none()
+}
+
+/// Commit `branch` points to in `repository`, if it exists.
+async fn query_head(
+    checkout: &Path,
+    repository: &str,
+    branch: &str,
+) -> anyhow::Result<Option<String>> {
+    let mut cmd = Command::new("git");
+    cmd.current_dir(checkout);
+    cmd.args(["ls-remote", "--heads", repository, branch]);
+
+    let output = utils::aquire_output(&mut cmd).await?;
+    Ok(output
+        .lines()
+        .find(|line| line.ends_with(&format!("refs/heads/{}", branch)))
+        .and_then(|line| line.split_whitespace().next())
+        .map(|head| head.to_string()))
+}
+
+/// Short hashes and subjects of the commits between `from` and `to`, newest first.
+async fn new_commits(
+    checkout: &Path,
+    branch: &str,
+    from: Option<&str>,
+    to: &str,
+) -> anyhow::Result<Vec<String>> {
+    let mut fetch = Command::new("git");
+    fetch.current_dir(checkout);
+    fetch.args(["fetch", "origin", branch]);
+    utils::execute("git", fetch).await?;
+
+    let mut log = Command::new("git");
+    log.current_dir(checkout);
+    log.args(["log", "--format=%h %s"]);
+    log.arg(format!("--max-count={}", MAX_COMMITS));
+    log.arg(match from {
+        Some(from) => format!("{}..{}", from, to),
+        None => to.to_string(),
+    });
+
+    Ok(utils::aquire_output(&mut log)
+        .await?
+        .lines()
+        .map(|line| line.to_string())
+        .collect())
+}
+
+/// Posts `msg` about the server called `name` to `channel` if there is one.
+async fn announce(http: &Http, channel: Option<ChannelId>, name: &str, msg: String) {
+    if let Some(channel) = channel {
+        let msg = MessageBuilder::new()
+            .push_bold_safe(name)
+            .push(": ")
+            .push(msg)
+            .build();
+        if let Err(e) = channel.say(http, msg).await {
+            log::error!("Failed to announce new commits: {}", e);
+        }
+    }
+}
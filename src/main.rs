@@ -1,21 +1,37 @@
+/// Records who ran which privileged command
+mod audit;
+/// Backups of the gameserver userdata
+mod backup;
 /// checks for permission to execute a specific command
 pub mod checks;
 /// All available discord commands
 mod commands;
 /// discord setup
 mod discord;
+/// Redeploys the followed branch on new commits
+mod follow;
 mod logger;
+/// Announces server status changes on discord
+mod notifications;
+/// Caches branches and commits to suggest as revs
+mod refs;
+/// Runs scheduled jobs
+mod scheduler;
 /// Veloren Server handling
 mod server;
 /// Bot Settings
 mod settings;
 /// Bot state
 mod state;
+/// Keeps the bot state up to date with the server
+mod tracker;
 mod utils;
 
 use anyhow::{Context, Result};
-use server::Server;
+use backup::Retention;
+use server::{Paths, Server};
 use settings::Settings;
+use std::{collections::BTreeMap, time::Duration};
 
 #[tokio::main]
 async fn main() -> Result<()> {
@@ -35,10 +51,32 @@ async fn main() -> Result<()> {
 
     utils::log_environment().await?;
 
-    let server = Server::new(&settings.repository)
+    let mut servers = BTreeMap::new();
+    for (name, gameserver) in settings
+        .gameservers()
+        .context("Invalid gameservers in settings.")?
+    {
+        let instance = (name != settings.gameserver_name).then_some(name.as_str());
+        let paths = Paths {
+            home: gameserver.checkout,
+            userdata: gameserver.userdata,
+            build_log: server::build_log_path(instance),
+            backups: settings.backup_dir.join(&name),
+        };
+        let server = Server::new(
+            &settings.repository,
+            paths,
+            Duration::from_secs(settings.shutdown_grace_period),
+            Retention {
+                keep_last: settings.backup_keep_last,
+                keep_daily: settings.backup_keep_daily,
+            },
+        )
         .await
-        .context("Failed to create server.")?;
-    discord::run(settings, server)
+        .with_context(|| format!("Failed to create server {}.", name))?;
+        servers.insert(name, server);
+    }
+    discord::run(settings, servers)
         .await
         .context("Failed to start discord.")
 }
@@ -0,0 +1,98 @@
+use crate::{
+    commands::info::create_diagnostics_msg,
+    server::{ServerStatus, Transition},
+    state::Rev,
+};
+use poise::serenity_prelude::{
+    ChannelId, Colour, CreateAttachment, CreateEmbed, CreateEmbedFooter, CreateMessage, Http,
+    MessageBuilder,
+};
+use std::{path::PathBuf, sync::Arc};
+use tokio::sync::broadcast::{self, error::RecvError};
+
+/// Posts every status change of the server called `name` to `channel` until the server is gone.
+pub async fn run(
+    http: Arc<Http>,
+    channel: ChannelId,
+    name: String,
+    build_log: PathBuf,
+    mut transitions: broadcast::Receiver<Transition>,
+) {
+    loop {
+        let transition = match transitions.recv().await {
+            Ok(transition) => transition,
+            Err(RecvError::Lagged(skipped)) => {
+                log::warn!("Skipped {} server status notifications.", skipped);
+                continue;
+            }
+            Err(RecvError::Closed) => return,
+        };
+
+        let mut msg = CreateMessage::new().embed(create_transition_msg(&name, &transition));
+        if *transition.to.unstaged() == ServerStatus::CompileFailed {
+            if let Ok(log) = CreateAttachment::path(&build_log).await {
+                msg = msg.add_file(log);
+            }
+        }
+        if let Err(e) = channel.send_message(&http, msg).await {
+            log::error!("Failed to send server status notification: {}", e);
+        }
+    }
+}
+
+fn create_transition_msg(name: &str, transition: &Transition) -> CreateEmbed {
+    let colour = match transition.to.unstaged() {
+        ServerStatus::Online | ServerStatus::Ready => Colour::DARK_GREEN,
+        ServerStatus::UpdateFailed | ServerStatus::CompileFailed | ServerStatus::RunFailed => {
+            Colour::RED
+        }
+        _ => Colour::GOLD,
+    };
+
+    let mut e = CreateEmbed::new()
+        .title(":bell: Veloren Server Status")
+        .colour(colour)
+        .footer(CreateEmbedFooter::new(name))
+        .description(
+            MessageBuilder::new()
+                .push_mono(transition.from.to_string())
+                .push(" → ")
+                .push_bold(transition.to.to_string())
+                .build(),
+        );
+
+    match &transition.rev {
+        Some(Rev::Commit(commit)) => {
+            e = e.field(
+                "Commit",
+                MessageBuilder::new().push_mono(commit).build(),
+                true,
+            );
+        }
+        Some(rev) => {
+            e = e.field(
+                rev.kind(),
+                MessageBuilder::new().push_mono(rev.to_string()).build(),
+                true,
+            );
+            if let Some(version) = &transition.version {
+                e = e.field(
+                    "Commit",
+                    MessageBuilder::new().push_mono(version).build(),
+                    true,
+                );
+            }
+        }
+        None => {}
+    }
+
+    if *transition.to.unstaged() == ServerStatus::CompileFailed {
+        e = e.field(
+            ":x: Errors",
+            create_diagnostics_msg(&transition.diagnostics),
+            false,
+        );
+    }
+
+    e
+}
@@ -0,0 +1,61 @@
+use crate::utils;
+use anyhow::Result;
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+use tokio::{process::Command, sync::Mutex};
+
+/// How often the branches are queried.
+const REFRESH_INTERVAL: Duration = Duration::from_secs(5 * 60);
+/// How many of the most recent commits are suggested.
+const MAX_COMMITS: usize = 25;
+
+/// Branches and recent commits of the repository to suggest as revs.
+#[derive(Debug, Default)]
+pub struct Refs {
+    /// Branches of the remote repository.
+    pub branches: Vec<String>,
+    /// Short hashes and subjects of the most recent commits fetched.
+    pub commits: Vec<(String, String)>,
+}
+
+impl Refs {
+    async fn query(repository: &str, checkout: &Path) -> Result<Self> {
+        let mut heads = Command::new("git");
+        heads.current_dir(checkout);
+        heads.args(["ls-remote", "--heads", repository]);
+        let branches = utils::aquire_output(&mut heads)
+            .await?
+            .lines()
+            .filter_map(|line| line.split_once("refs/heads/"))
+            .map(|(_, branch)| branch.to_string())
+            .collect();
+
+        let mut log = Command::new("git");
+        log.current_dir(checkout);
+        log.args(["log", "--remotes", "--date-order", "--format=%h %s"]);
+        log.arg(format!("--max-count={}", MAX_COMMITS));
+        let commits = utils::aquire_output(&mut log)
+            .await?
+            .lines()
+            .filter_map(|line| line.split_once(' '))
+            .map(|(hash, subject)| (hash.to_string(), subject.to_string()))
+            .collect();
+
+        Ok(Self { branches, commits })
+    }
+}
+
+/// Keeps `refs` up to date with the repository, as fetched into `checkout`.
+pub async fn run(refs: Arc<Mutex<Refs>>, repository: String, checkout: PathBuf) {
+    let mut interval = tokio::time::interval(REFRESH_INTERVAL);
+    loop {
+        interval.tick().await;
+        match Refs::query(&repository, &checkout).await {
+            Ok(queried) => *refs.lock().await = queried,
+            Err(e) => log::warn!("Failed to query branches: {}", e),
+        }
+    }
+}
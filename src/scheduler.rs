@@ -0,0 +1,173 @@
+use crate::{
+    server::Server,
+    state::{Action, Job, State},
+};
+use anyhow::Result;
+use poise::serenity_prelude::{ChannelId, Http, MessageBuilder};
+use std::{collections::HashSet, sync::Arc, time::Duration};
+use tokio::sync::Mutex;
+
+/// How often the schedule is checked.
+const TICK: Duration = Duration::from_secs(20);
+/// Seconds before a job players get warned.
+const COUNTDOWN: [i64; 3] = [10 * 60, 5 * 60, 60];
+/// Runs missed by more seconds than this (e.g. while the bot was offline) are skipped.
+const MAX_DELAY: i64 = 10 * 60;
+
+/// Runs the jobs scheduled in `state` of the server called `name` and warns about them beforehand.
+pub async fn run(
+    http: Arc<Http>,
+    channel: Option<ChannelId>,
+    name: String,
+    state: Arc<Mutex<State>>,
+    server: Arc<Mutex<Server>>,
+    repository: String,
+) {
+    let mut interval = tokio::time::interval(TICK);
+    // Warnings which have been sent already by job id, run and countdown.
+    let mut warned = HashSet::new();
+    loop {
+        interval.tick().await;
+
+        let now = chrono::Utc::now().timestamp();
+        let jobs = state.lock().await.schedule().to_vec();
+        for job in jobs {
+            let left = job.next - now;
+            if left > 0 {
+                let countdown = COUNTDOWN
+                    .iter()
+                    .copied()
+                    .filter(|countdown| left <= *countdown)
+                    .min();
+                if let Some(countdown) = countdown {
+                    if warned.insert((job.id, job.next, countdown)) {
+                        warn(&http, channel, &name, &server, &job, left).await;
+                    }
+                }
+                continue;
+            }
+
+            // The schedule might have changed while warning about or running other jobs.
+            match state.lock().await.complete_job(&job, now).await {
+                Ok(true) => {}
+                Ok(false) => continue,
+                Err(e) => {
+                    log::error!("Failed to complete scheduled job: {}", e);
+                    continue;
+                }
+            }
+            if -left > MAX_DELAY {
+                log::warn!("Skipped scheduled {}, it is overdue.", job.action);
+                continue;
+            }
+
+            log::info!("Running scheduled {}...", job.action);
+            announce(
+                &http,
+                channel,
+                &name,
+                MessageBuilder::new()
+                    .push(":alarm_clock: Running scheduled ")
+                    .push_bold_safe(job.action.to_string())
+                    .push(".")
+                    .build(),
+            )
+            .await;
+            if let Err(e) = execute(&job, &state, &server, &repository).await {
+                log::error!("Failed to run scheduled {}: {}", job.action, e);
+                announce(
+                    &http,
+                    channel,
+                    &name,
+                    format!(":x: Scheduled {} failed: {}", job.action, e),
+                )
+                .await;
+            }
+        }
+        warned.retain(|(_, next, _)| *next > now);
+    }
+}
+
+async fn execute(
+    job: &Job,
+    state: &Arc<Mutex<State>>,
+    server: &Arc<Mutex<Server>>,
+    repository: &str,
+) -> Result<()> {
+    let mut server_guard = server.lock().await;
+    let mut state = state.lock().await;
+
+    match &job.action {
+        Action::Restart => {
+            server_guard
+                .restart(
+                    state.rev(),
+                    state.args(),
+                    state.cargo_args(),
+                    &state.envs(),
+                    state.restart_policy(),
+                )
+                .await;
+        }
+        Action::Prune => {
+            if !server_guard
+                .clean(
+                    state.rev(),
+                    state.args(),
+                    state.cargo_args(),
+                    &state.envs(),
+                    state.restart_policy(),
+                )
+                .await
+            {
+                anyhow::bail!("Failed to clean");
+            }
+        }
+        Action::Rev(rev) => {
+            let checkout = server_guard.paths().home.clone();
+            if !state.set_rev(rev, repository, &checkout, None).await? {
+                anyhow::bail!("`{}` does not exist", rev);
+            }
+            server_guard.deploy(server, &state).await;
+        }
+    }
+
+    Ok(())
+}
+
+/// Tells the players and `channel` that `job` runs in `left` seconds.
+async fn warn(
+    http: &Http,
+    channel: Option<ChannelId>,
+    name: &str,
+    server: &Arc<Mutex<Server>>,
+    job: &Job,
+    left: i64,
+) {
+    let minutes = (left + 59) / 60;
+    let warning = format!(
+        "Scheduled {} in {} minute{}.",
+        job.action,
+        minutes,
+        if minutes == 1 { "" } else { "s" }
+    );
+    log::info!("{}", warning);
+
+    let broadcast = server.lock().await.broadcast(&warning);
+    broadcast.await;
+    announce(http, channel, name, format!(":hourglass: {}", warning)).await;
+}
+
+/// Posts `msg` about the server called `name` to `channel` if there is one.
+async fn announce(http: &Http, channel: Option<ChannelId>, name: &str, msg: String) {
+    if let Some(channel) = channel {
+        let msg = MessageBuilder::new()
+            .push_bold_safe(name)
+            .push(": ")
+            .push(msg)
+            .build();
+        if let Err(e) = channel.say(http, msg).await {
+            log::error!("Failed to announce scheduled job: {}", e);
+        }
+    }
+}
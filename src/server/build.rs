@@ -0,0 +1,272 @@
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+/// Binary of the gameserver.
+pub const BINARY: &str = "veloren-server-cli";
+
+/// Where the output of the last build is stored.
+/// Builds of other than the default instance get their `instance` name appended.
+pub fn log_path(instance: Option<&str>) -> PathBuf {
+    let path =
+        PathBuf::from(std::env::var("BOT_BUILD_LOG").unwrap_or_else(|_| "build.log".to_string()));
+    match instance {
+        Some(instance) => {
+            let mut name = path.file_stem().unwrap_or_default().to_os_string();
+            name.push(format!("-{}.log", instance));
+            path.with_file_name(name)
+        }
+        None => path,
+    }
+}
+
+/// An error reported by cargo or rustc.
+#[derive(Debug, Clone, Ord, PartialOrd, Eq, PartialEq)]
+pub struct Diagnostic {
+    pub message: String,
+    /// `file:line:column` the error points to.
+    pub location: Option<String>,
+}
+
+impl std::fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.location {
+            Some(location) => write!(f, "{}: {}", location, self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+/// How far a build got.
+#[derive(Debug, Clone, Copy, Ord, PartialOrd, Eq, PartialEq)]
+pub struct Progress {
+    /// Crates which have been compiled or were up to date.
+    pub units: usize,
+    pub started: Instant,
+    /// Time from the start until the last crate finished.
+    pub elapsed: Duration,
+}
+
+/// Messages emitted by `cargo build --message-format=json`.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "reason", rename_all = "kebab-case")]
+enum CargoMessage {
+    CompilerMessage {
+        message: CompilerMessage,
+    },
+    CompilerArtifact {
+        target: Target,
+        executable: Option<PathBuf>,
+    },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Deserialize)]
+struct Target {
+    name: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct CompilerMessage {
+    message: String,
+    level: String,
+    rendered: Option<String>,
+    spans: Vec<Span>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Span {
+    file_name: String,
+    line_start: usize,
+    column_start: usize,
+    is_primary: bool,
+}
+
+/// Collects the output of a cargo build.
+#[derive(Debug)]
+pub struct BuildLog {
+    lines: Vec<String>,
+    diagnostics: Vec<Diagnostic>,
+    progress: Progress,
+    /// The built gameserver binary.
+    executable: Option<PathBuf>,
+}
+
+impl BuildLog {
+    pub fn new() -> Self {
+        Self {
+            lines: Vec::new(),
+            diagnostics: Vec::new(),
+            progress: Progress {
+                units: 0,
+                started: Instant::now(),
+                elapsed: Duration::ZERO,
+            },
+            executable: None,
+        }
+    }
+
+    /// Logs a line of cargo output and extracts errors from it.
+    ///
+    /// Returns whether the line finished another crate.
+    pub fn push(&mut self, line: String) -> bool {
+        match serde_json::from_str::<CargoMessage>(&line) {
+            Ok(CargoMessage::CompilerMessage { message }) => self.push_compiler_message(message),
+            Ok(CargoMessage::CompilerArtifact { target, executable }) => {
+                if target.name == BINARY && executable.is_some() {
+                    self.executable = executable;
+                }
+                self.progress.units += 1;
+                self.progress.elapsed = self.progress.started.elapsed();
+                return true;
+            }
+            Ok(CargoMessage::Other) => {}
+            // Not a json message, e.g. cargo's own progress & errors.
+            Err(_) => {
+                let line = line.trim_start().trim_end();
+                log::info!("[cargo] {}", line);
+                if let Some(message) = line.strip_prefix("error: ") {
+                    if !message.starts_with("could not compile") {
+                        self.diagnostics.push(Diagnostic {
+                            message: message.to_string(),
+                            location: None,
+                        });
+                    }
+                }
+                self.lines.push(line.to_string());
+            }
+        }
+        false
+    }
+
+    fn push_compiler_message(&mut self, message: CompilerMessage) {
+        let rendered = message.rendered.as_deref().unwrap_or(&message.message);
+        for line in rendered.lines() {
+            log::info!("[cargo] {}", line);
+            self.lines.push(line.to_string());
+        }
+
+        if message.level != "error" || message.message.starts_with("aborting due to") {
+            return;
+        }
+        let location = message
+            .spans
+            .iter()
+            .find(|span| span.is_primary)
+            .map(|span| {
+                format!(
+                    "{}:{}:{}",
+                    span.file_name, span.line_start, span.column_start
+                )
+            });
+        self.diagnostics.push(Diagnostic {
+            message: message.message,
+            location,
+        });
+    }
+
+    pub fn progress(&self) -> Progress {
+        self.progress
+    }
+
+    /// Path to the built gameserver binary.
+    pub fn executable(&self) -> Option<&PathBuf> {
+        self.executable.as_ref()
+    }
+
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+
+    /// Writes the collected output to [`log_path`].
+    pub async fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            let _ = tokio::fs::create_dir_all(parent).await;
+        }
+        tokio::fs::write(path, self.lines.join("\n"))
+            .await
+            .context("Failed to write build log")?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compiler_errors_are_diagnostics() {
+        let mut log = BuildLog::new();
+        let line = r#"{"reason":"compiler-message","message":{"message":"cannot find value `x` in this scope","level":"error","rendered":"error[E0425]: cannot find value `x` in this scope\n --> server/src/lib.rs:12:5\n","spans":[{"file_name":"server/src/lib.rs","line_start":12,"column_start":5,"is_primary":true}]}}"#;
+        assert!(!log.push(line.to_string()));
+        assert_eq!(
+            log.diagnostics(),
+            [Diagnostic {
+                message: "cannot find value `x` in this scope".to_string(),
+                location: Some("server/src/lib.rs:12:5".to_string()),
+            }]
+        );
+        assert_eq!(log.lines.len(), 2);
+    }
+
+    #[test]
+    fn warnings_and_summaries_are_no_diagnostics() {
+        let mut log = BuildLog::new();
+        log.push(r#"{"reason":"compiler-message","message":{"message":"unused variable: `y`","level":"warning","rendered":null,"spans":[]}}"#.to_string());
+        log.push(r#"{"reason":"compiler-message","message":{"message":"aborting due to 1 previous error","level":"error","rendered":null,"spans":[]}}"#.to_string());
+        log.push(
+            "error: could not compile `veloren-server` (lib) due to 1 previous error".to_string(),
+        );
+        assert!(log.diagnostics().is_empty());
+    }
+
+    #[test]
+    fn cargo_errors_are_diagnostics() {
+        let mut log = BuildLog::new();
+        log.push("   Compiling veloren-common v0.10.0".to_string());
+        log.push("error: failed to select a version for `serde`.".to_string());
+        assert_eq!(
+            log.diagnostics(),
+            [Diagnostic {
+                message: "failed to select a version for `serde`.".to_string(),
+                location: None,
+            }]
+        );
+        assert_eq!(
+            log.lines,
+            [
+                "Compiling veloren-common v0.10.0",
+                "error: failed to select a version for `serde`."
+            ]
+        );
+    }
+
+    #[test]
+    fn artifacts_count_as_progress() {
+        let mut log = BuildLog::new();
+        let lib = r#"{"reason":"compiler-artifact","target":{"name":"veloren-common"},"executable":null}"#;
+        let bin = r#"{"reason":"compiler-artifact","target":{"name":"veloren-server-cli"},"executable":"/veloren/target/release/veloren-server-cli"}"#;
+        assert!(log.push(lib.to_string()));
+        assert_eq!(log.executable(), None);
+        assert!(log.push(bin.to_string()));
+        assert_eq!(log.progress().units, 2);
+        assert_eq!(
+            log.executable(),
+            Some(&PathBuf::from("/veloren/target/release/veloren-server-cli"))
+        );
+    }
+
+    #[test]
+    fn other_messages_are_no_progress() {
+        let mut log = BuildLog::new();
+        assert!(!log.push(r#"{"reason":"build-script-executed","package_id":"x"}"#.to_string()));
+        assert!(!log.push(r#"{"reason":"build-finished","success":true}"#.to_string()));
+        assert!(!log.push("    Finished `release` profile".to_string()));
+        assert_eq!(log.progress().units, 0);
+        assert_eq!(log.progress().elapsed, Duration::ZERO);
+    }
+}
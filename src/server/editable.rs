@@ -0,0 +1,1000 @@
+use chrono::{DateTime, SecondsFormat, Utc};
+use std::ops::Range;
+
+/// Version of the editable settings format which can be edited.
+const VERSION: &str = "V1";
+/// Content of a list which doesn't exist yet.
+const EMPTY: &str = "V1({})";
+/// Indentation of the gameserver's pretty printed RON.
+const INDENT: &str = "    ";
+/// Account recorded as having whitelisted players, the bot has none of its own.
+const BOT_UUID: &str = "00000000-0000-0000-0000-000000000000";
+/// Name recorded as having whitelisted players.
+const BOT_USERNAME: &str = "veloren-server-bot";
+
+/// Editable settings of the gameserver which hold players by uuid.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum List {
+    Admins,
+    Banlist,
+    Whitelist,
+}
+
+/// Roles the gameserver knows for its admins.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AdminRole {
+    Moderator,
+    Admin,
+}
+
+impl std::fmt::Display for AdminRole {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Moderator => write!(f, "Moderator"),
+            Self::Admin => write!(f, "Admin"),
+        }
+    }
+}
+
+/// What gets recorded about a player added to a list.
+#[derive(Debug, Clone)]
+pub enum Record {
+    Admin(AdminRole),
+    Ban {
+        reason: String,
+        until: Option<DateTime<Utc>>,
+    },
+    Whitelist,
+}
+
+/// A player on a list.
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub uuid: String,
+    /// Name the player had when added, if recorded.
+    pub username: Option<String>,
+    /// Role of admins, reason and end of bans.
+    pub detail: Option<String>,
+}
+
+impl Entry {
+    /// Whether `player` is the uuid or name of this one.
+    fn is(&self, player: &str) -> bool {
+        self.uuid.eq_ignore_ascii_case(player)
+            || self
+                .username
+                .as_ref()
+                .is_some_and(|username| username.eq_ignore_ascii_case(player))
+    }
+}
+
+/// The players on `list` in `content`, which is the file or `None` if there is none yet.
+/// Bans which have been lifted are left out.
+pub fn entries(content: Option<&str>, list: List) -> Result<Vec<Entry>, String> {
+    let content = content.unwrap_or(EMPTY);
+    let root = parse(content)?;
+    let (_, players) = players(&root)?;
+    Ok(players
+        .iter()
+        .filter_map(|(key, value)| entry(content, list, key, value))
+        .collect())
+}
+
+/// Adds the player with `uuid` and `username` to the list of `record` in `content`.
+/// Replaces the record if the player is on the list already. Returns the new content.
+pub fn add(
+    content: Option<&str>,
+    uuid: &str,
+    username: &str,
+    record: &Record,
+    now: DateTime<Utc>,
+) -> Result<String, String> {
+    let content = content.unwrap_or(EMPTY);
+    let root = parse(content)?;
+    let (map, players) = players(&root)?;
+    let existing = players
+        .iter()
+        .find(|(key, _)| matches!(&key.value, Value::Str(key) if key.eq_ignore_ascii_case(uuid)));
+
+    let date = string(&now.to_rfc3339_opts(SecondsFormat::Secs, true));
+    let value = match record {
+        Record::Admin(role) => format!(
+            "(username_when_admined: Some({}), date: {}, role: {})",
+            string(username),
+            date,
+            role
+        ),
+        Record::Whitelist => format!(
+            "(date: {}, info: Some((username_when_whitelisted: {}, whitelisted_by: {}, \
+            whitelisted_by_username: {}, whitelisted_by_role: {})))",
+            date,
+            string(username),
+            string(BOT_UUID),
+            string(BOT_USERNAME),
+            AdminRole::Admin
+        ),
+        Record::Ban { reason, until } => {
+            let current = format!(
+                "(username_when_performed: {}, action: Ban((reason: {}, info: None, end_date: {})), \
+                date: {})",
+                string(username),
+                string(reason),
+                match until {
+                    Some(until) => format!(
+                        "Some({})",
+                        string(&until.to_rfc3339_opts(SecondsFormat::Secs, true))
+                    ),
+                    None => "None".to_string(),
+                },
+                date
+            );
+            // Keep the previous records of the player.
+            let mut history = Vec::new();
+            if let Some((_, entry)) = existing {
+                if let Some(Value::Tuple(_, records)) = entry.field("history").map(|n| &n.value) {
+                    history.extend(records.iter().map(|record| &content[record.span.clone()]));
+                }
+                if let Some(current) = entry.field("current") {
+                    history.push(&content[current.span.clone()]);
+                }
+            }
+            format!("(current: {}, history: [{}])", current, history.join(", "))
+        }
+    };
+
+    let new = match (existing, players.last()) {
+        (Some((_, entry)), _) => splice(content, entry.span.clone(), &value),
+        (None, Some((key, last))) => {
+            let indent = indentation(content, key.span.start);
+            let line = format!("{}\"{}\": {}", indent, uuid, value);
+            let end = skip_inline_whitespace(content, last.span.end);
+            match content[end..].starts_with(',') {
+                true => splice(content, end + 1..end + 1, &format!("\n{},", line)),
+                false => splice(
+                    content,
+                    last.span.end..last.span.end,
+                    &format!(",\n{}", line),
+                ),
+            }
+        }
+        (None, None) => {
+            let indent = indentation(content, map.span.start);
+            let map_content = format!(
+                "{{\n{}{}\"{}\": {},\n{}}}",
+                indent, INDENT, uuid, value, indent
+            );
+            splice(content, map.span.clone(), &map_content)
+        }
+    };
+    checked(new)
+}
+
+/// Removes `player`, given by uuid or name, from `list` in `content`.
+/// Returns the new content and the removed entry, `None` if the player isn't on the list.
+pub fn remove(
+    content: Option<&str>,
+    list: List,
+    player: &str,
+) -> Result<Option<(String, Entry)>, String> {
+    let content = match content {
+        Some(content) => content,
+        None => return Ok(None),
+    };
+    let root = parse(content)?;
+    let (_, players) = players(&root)?;
+    let found = players.iter().find_map(|(key, value)| {
+        entry(content, list, key, value)
+            .filter(|entry| entry.is(player))
+            .map(|entry| (key, value, entry))
+    });
+    let (key, value, entry) = match found {
+        Some(found) => found,
+        None => return Ok(None),
+    };
+
+    // Take the whole line if the entry has one on its own.
+    let mut start = key.span.start;
+    while start > 0 && matches!(content.as_bytes()[start - 1], b' ' | b'\t') {
+        start -= 1;
+    }
+    let own_line = start == 0 || content.as_bytes()[start - 1] == b'\n';
+    let mut end = skip_inline_whitespace(content, value.span.end);
+    if content[end..].starts_with(',') {
+        end = skip_inline_whitespace(content, end + 1);
+    }
+    if own_line {
+        if content[end..].starts_with("\r\n") {
+            end += 2;
+        } else if content[end..].starts_with('\n') {
+            end += 1;
+        }
+    } else {
+        start = key.span.start;
+    }
+
+    let new = splice(content, start..end, "");
+    Ok(Some((checked(new)?, entry)))
+}
+
+/// `new` if it can be parsed, also by `ron` itself. Never hand out a file the gameserver can't read.
+fn checked(new: String) -> Result<String, String> {
+    parse(&new)?;
+    ron::from_str::<ron::Value>(&new).map_err(|e| format!("The edited file is invalid: {}", e))?;
+    Ok(new)
+}
+
+/// Reads `list` off the entry with `key` and `value`. `None` for lifted bans and invalid keys.
+fn entry(content: &str, list: List, key: &Node, value: &Node) -> Option<Entry> {
+    let uuid = match &key.value {
+        Value::Str(uuid) => uuid.clone(),
+        _ => return None,
+    };
+    let detail = match list {
+        List::Admins => value
+            .field("role")
+            .map(|role| content[role.span.clone()].to_string()),
+        List::Banlist => {
+            let action = value.field("current")?.field("action")?;
+            let ban = match &action.value {
+                Value::Tuple(Some(name), ban) if name == "Ban" => ban.first()?,
+                _ => return None,
+            };
+            let reason = ban.field("reason").and_then(Node::str).unwrap_or_default();
+            match ban.field("end_date").and_then(Node::str) {
+                Some(end) => Some(format!("{} (until {})", reason, end)),
+                None => Some(reason.to_string()),
+            }
+        }
+        List::Whitelist => None,
+    };
+
+    Some(Entry {
+        uuid,
+        username: value.username(),
+        detail,
+    })
+}
+
+/// The map of players in the versioned file and its entries, with their uuids as keys.
+fn players(root: &Node) -> Result<(&Node, &[Pair]), String> {
+    match &root.value {
+        Value::Tuple(Some(version), inner) if version == VERSION => match inner.as_slice() {
+            [map @ Node {
+                value: Value::Map(players),
+                ..
+            }] => Ok((map, players)),
+            _ => Err(format!("Expected a map in {}(...).", VERSION)),
+        },
+        Value::Tuple(Some(version), _) => Err(format!(
+            "The file has format {}, only {} can be edited.",
+            version, VERSION
+        )),
+        _ => Err(format!(
+            "Expected a versioned map like {}({{ ... }}).",
+            VERSION
+        )),
+    }
+}
+
+/// `value` as RON string.
+fn string(value: &str) -> String {
+    ron::to_string(value).unwrap_or_else(|_| format!("{:?}", value))
+}
+
+fn splice(content: &str, range: Range<usize>, with: &str) -> String {
+    let mut new = String::with_capacity(content.len() + with.len());
+    new.push_str(&content[..range.start]);
+    new.push_str(with);
+    new.push_str(&content[range.end..]);
+    new
+}
+
+/// Whitespace the line containing `pos` starts with.
+fn indentation(content: &str, pos: usize) -> &str {
+    let line = content[..pos].rfind('\n').map_or(0, |newline| newline + 1);
+    let indented = content[line..pos]
+        .find(|c: char| c != ' ' && c != '\t')
+        .map_or(pos, |text| line + text);
+    &content[line..indented]
+}
+
+fn skip_inline_whitespace(content: &str, mut pos: usize) -> usize {
+    while matches!(content.as_bytes().get(pos), Some(b' ' | b'\t')) {
+        pos += 1;
+    }
+    pos
+}
+
+/// A RON value and where it is in the file, so it can be edited without touching the rest.
+#[derive(Debug)]
+struct Node {
+    span: Range<usize>,
+    value: Value,
+}
+
+/// Key and value in a map.
+type Pair = (Node, Node);
+
+#[derive(Debug)]
+enum Value {
+    Str(String),
+    /// Numbers, booleans, unit variants and the like.
+    Atom,
+    Map(Vec<Pair>),
+    /// Named or anonymous struct.
+    Struct(Vec<(String, Node)>),
+    /// Named or anonymous tuple, variant with content or list.
+    Tuple(Option<String>, Vec<Node>),
+}
+
+impl Node {
+    fn field(&self, name: &str) -> Option<&Node> {
+        match &self.value {
+            Value::Struct(fields) => fields
+                .iter()
+                .find(|(field, _)| field == name)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    /// The string, also if it's optional.
+    fn str(&self) -> Option<&str> {
+        match &self.value {
+            Value::Str(string) => Some(string),
+            Value::Tuple(Some(some), inner) if some == "Some" => inner.first()?.str(),
+            _ => None,
+        }
+    }
+
+    /// The first `username_when_*` anywhere in this one, e.g. `username_when_admined`.
+    fn username(&self) -> Option<String> {
+        match &self.value {
+            Value::Struct(fields) => {
+                fields
+                    .iter()
+                    .find_map(|(field, value)| match field.starts_with("username_when_") {
+                        true => value.str().map(str::to_string),
+                        false => value.username(),
+                    })
+            }
+            Value::Tuple(_, items) => items.iter().find_map(Node::username),
+            _ => None,
+        }
+    }
+}
+
+/// Parses the RON in `content`.
+fn parse(content: &str) -> Result<Node, String> {
+    let mut parser = Parser { content, pos: 0 };
+    parser.skip_whitespace();
+    // Extensions like `#![enable(implicit_some)]`.
+    while parser.rest().starts_with("#!") {
+        match parser.rest().find(']') {
+            Some(end) => parser.pos += end + 1,
+            None => return Err(parser.error("Unclosed attribute")),
+        }
+        parser.skip_whitespace();
+    }
+    let root = parser.value()?;
+    parser.skip_whitespace();
+    match parser.rest().is_empty() {
+        true => Ok(root),
+        false => Err(parser.error("Unexpected content after the value")),
+    }
+}
+
+struct Parser<'a> {
+    content: &'a str,
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn rest(&self) -> &str {
+        &self.content[self.pos..]
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.rest().chars().next()
+    }
+
+    fn error(&self, msg: &str) -> String {
+        let before = &self.content[..self.pos];
+        let line = before.matches('\n').count() + 1;
+        let column = before.len() - before.rfind('\n').map_or(0, |newline| newline + 1) + 1;
+        format!("{} at line {}, column {}.", msg, line, column)
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), String> {
+        self.skip_whitespace();
+        match self.peek() == Some(c) {
+            true => {
+                self.pos += c.len_utf8();
+                Ok(())
+            }
+            false => Err(self.error(&format!("Expected `{}`", c))),
+        }
+    }
+
+    /// Skips whitespace and comments.
+    fn skip_whitespace(&mut self) {
+        loop {
+            let whitespace = self.rest().len() - self.rest().trim_start().len();
+            self.pos += whitespace;
+            if self.rest().starts_with("//") {
+                self.pos += self.rest().find('\n').unwrap_or(self.rest().len());
+            } else if self.rest().starts_with("/*") {
+                let mut depth = 0;
+                while !self.rest().is_empty() {
+                    if self.rest().starts_with("/*") {
+                        depth += 1;
+                        self.pos += 2;
+                    } else if self.rest().starts_with("*/") {
+                        depth -= 1;
+                        self.pos += 2;
+                        if depth == 0 {
+                            break;
+                        }
+                    } else {
+                        self.pos += self.peek().map_or(1, char::len_utf8);
+                    }
+                }
+            } else {
+                break;
+            }
+        }
+    }
+
+    fn value(&mut self) -> Result<Node, String> {
+        self.skip_whitespace();
+        let start = self.pos;
+        let value = match self.peek() {
+            Some('"') => Value::Str(self.string()?),
+            Some('r') if self.rest()[1..].starts_with(['"', '#']) => Value::Str(self.raw_string()?),
+            Some('{') => self.map()?,
+            Some('(') => self.parenthesized(None)?,
+            Some('[') => {
+                self.pos += 1;
+                Value::Tuple(None, self.items(']')?)
+            }
+            Some(c) if c.is_alphabetic() || c == '_' => {
+                let name = self.identifier();
+                let after = self.pos;
+                self.skip_whitespace();
+                match self.peek() {
+                    Some('(') => self.parenthesized(Some(name))?,
+                    _ => {
+                        self.pos = after;
+                        Value::Atom
+                    }
+                }
+            }
+            Some('\'') => {
+                self.pos += 1;
+                while let Some(c) = self.peek() {
+                    self.pos += c.len_utf8();
+                    match c {
+                        '\\' => self.pos += self.peek().map_or(0, char::len_utf8),
+                        '\'' => break,
+                        _ => {}
+                    }
+                }
+                Value::Atom
+            }
+            Some(_) => {
+                let len = self
+                    .rest()
+                    .find(|c: char| c.is_whitespace() || ",:)]}".contains(c))
+                    .unwrap_or(self.rest().len());
+                if len == 0 {
+                    return Err(self.error("Expected a value"));
+                }
+                self.pos += len;
+                Value::Atom
+            }
+            None => return Err(self.error("Unexpected end of file")),
+        };
+
+        Ok(Node {
+            span: start..self.pos,
+            value,
+        })
+    }
+
+    fn identifier(&mut self) -> String {
+        let len = self
+            .rest()
+            .find(|c: char| !c.is_alphanumeric() && c != '_')
+            .unwrap_or(self.rest().len());
+        self.pos += len;
+        self.content[self.pos - len..self.pos].to_string()
+    }
+
+    /// Finds the end of the string and leaves decoding its escapes to `ron`.
+    fn string(&mut self) -> Result<String, String> {
+        let start = self.pos;
+        self.pos += 1;
+        loop {
+            let c = match self.peek() {
+                Some(c) => c,
+                None => {
+                    self.pos = start;
+                    return Err(self.error("Unclosed string"));
+                }
+            };
+            self.pos += c.len_utf8();
+            match c {
+                '"' => break,
+                '\\' => self.pos += self.peek().map_or(0, char::len_utf8),
+                _ => {}
+            }
+        }
+        let content = self.content;
+        ron::from_str(&content[start..self.pos]).map_err(|e| {
+            self.pos = start;
+            self.error(&format!("Invalid string ({})", e.code))
+        })
+    }
+
+    fn raw_string(&mut self) -> Result<String, String> {
+        let start = self.pos;
+        self.pos += 1;
+        let hashes = self.rest().len() - self.rest().trim_start_matches('#').len();
+        self.pos += hashes;
+        self.expect('"')?;
+        let end = format!("\"{}", "#".repeat(hashes));
+        match self.rest().find(&end) {
+            Some(len) => {
+                let string = self.rest()[..len].to_string();
+                self.pos += len + end.len();
+                Ok(string)
+            }
+            None => {
+                self.pos = start;
+                Err(self.error("Unclosed string"))
+            }
+        }
+    }
+
+    fn map(&mut self) -> Result<Value, String> {
+        self.pos += 1;
+        let mut entries = Vec::new();
+        loop {
+            self.skip_whitespace();
+            if self.peek() == Some('}') {
+                self.pos += 1;
+                return Ok(Value::Map(entries));
+            }
+            let key = self.value()?;
+            self.expect(':')?;
+            let value = self.value()?;
+            entries.push((key, value));
+            self.separator('}')?;
+        }
+    }
+
+    /// Struct or tuple in parentheses, named `name`.
+    fn parenthesized(&mut self, name: Option<String>) -> Result<Value, String> {
+        self.pos += 1;
+        self.skip_whitespace();
+
+        // Structs start with `field:`.
+        let start = self.pos;
+        let is_struct = match self.peek() {
+            Some(c) if c.is_alphabetic() || c == '_' => {
+                self.identifier();
+                self.skip_whitespace();
+                let is_struct = self.rest().starts_with(':') && !self.rest().starts_with("::");
+                self.pos = start;
+                is_struct
+            }
+            _ => false,
+        };
+        if !is_struct {
+            return Ok(Value::Tuple(name, self.items(')')?));
+        }
+
+        let mut fields = Vec::new();
+        loop {
+            self.skip_whitespace();
+            if self.peek() == Some(')') {
+                self.pos += 1;
+                return Ok(Value::Struct(fields));
+            }
+            let field = self.identifier();
+            if field.is_empty() {
+                return Err(self.error("Expected a field name"));
+            }
+            self.expect(':')?;
+            fields.push((field, self.value()?));
+            self.separator(')')?;
+        }
+    }
+
+    /// Comma separated values up to `close`.
+    fn items(&mut self, close: char) -> Result<Vec<Node>, String> {
+        let mut items = Vec::new();
+        loop {
+            self.skip_whitespace();
+            if self.peek() == Some(close) {
+                self.pos += 1;
+                return Ok(items);
+            }
+            items.push(self.value()?);
+            self.separator(close)?;
+        }
+    }
+
+    /// Consumes the comma after an item, unless it's the last one before `close`.
+    fn separator(&mut self, close: char) -> Result<(), String> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some(',') => {
+                self.pos += 1;
+                Ok(())
+            }
+            Some(c) if c == close => Ok(()),
+            _ => Err(self.error(&format!("Expected `,` or `{}`", close))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    const ALICE: &str = "f81d4fae-7dec-11d0-a765-00a0c91e6bf6";
+    const BOB: &str = "0b2e5f9a-1c3d-4e5f-8a9b-0c1d2e3f4a5b";
+
+    const ADMINS: &str = r#"V1({
+    "f81d4fae-7dec-11d0-a765-00a0c91e6bf6": (
+        username_when_admined: Some("alice"),
+        date: "2024-01-31T04:00:00Z",
+        role: Admin,
+    ),
+})
+"#;
+
+    fn now() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 2, 1, 12, 0, 0).unwrap()
+    }
+
+    /// The content as the gameserver would read it.
+    fn ron(content: &str) -> ron::Value {
+        ron::from_str(content).unwrap_or_else(|e| panic!("{}\n{}", e, content))
+    }
+
+    fn usernames(content: &str, list: List) -> Vec<Option<String>> {
+        entries(Some(content), list)
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.username)
+            .collect()
+    }
+
+    #[test]
+    fn add_without_file() {
+        let new = add(
+            None,
+            ALICE,
+            "alice",
+            &Record::Admin(AdminRole::Admin),
+            now(),
+        )
+        .unwrap();
+        ron(&new);
+        let entries = entries(Some(&new), List::Admins).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].uuid, ALICE);
+        assert_eq!(entries[0].username.as_deref(), Some("alice"));
+    }
+
+    #[test]
+    fn add_to_empty() {
+        let new = add(
+            Some("V1({})\n"),
+            ALICE,
+            "alice",
+            &Record::Admin(AdminRole::Moderator),
+            now(),
+        )
+        .unwrap();
+        ron(&new);
+        assert!(new.ends_with("})\n"));
+        let entries = entries(Some(&new), List::Admins).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].detail.as_deref(), Some("Moderator"));
+    }
+
+    #[test]
+    fn add_to_single_entry() {
+        let content = ADMINS.replace("    ),\n})", "    )\n})");
+        let new = add(
+            Some(&content),
+            BOB,
+            "bob",
+            &Record::Admin(AdminRole::Admin),
+            now(),
+        )
+        .unwrap();
+        ron(&new);
+        assert!(new.starts_with(&content[..content.find("    )\n").unwrap()]));
+        assert_eq!(
+            usernames(&new, List::Admins),
+            [Some("alice".to_string()), Some("bob".to_string())]
+        );
+    }
+
+    #[test]
+    fn add_after_trailing_comma() {
+        let new = add(
+            Some(ADMINS),
+            BOB,
+            "bob",
+            &Record::Admin(AdminRole::Admin),
+            now(),
+        )
+        .unwrap();
+        ron(&new);
+        assert!(new.contains("    ),\n    \"0b2e5f9a"));
+        assert!(!new.contains(",,"));
+        assert_eq!(usernames(&new, List::Admins).len(), 2);
+    }
+
+    #[test]
+    fn add_keeps_comments() {
+        let content = ADMINS
+            .replace("V1({\n", "// Admins\nV1({ /* by uuid */\n")
+            .replace("role: Admin,", "role: Admin, // the owner");
+        let new = add(
+            Some(&content),
+            BOB,
+            "bob",
+            &Record::Admin(AdminRole::Moderator),
+            now(),
+        )
+        .unwrap();
+        ron(&new);
+        for comment in ["// Admins", "/* by uuid */", "// the owner"] {
+            assert!(new.contains(comment), "{}", new);
+        }
+        assert_eq!(usernames(&new, List::Admins).len(), 2);
+    }
+
+    #[test]
+    fn add_replaces_existing() {
+        let new = add(
+            Some(ADMINS),
+            &ALICE.to_uppercase(),
+            "alice",
+            &Record::Admin(AdminRole::Moderator),
+            now(),
+        )
+        .unwrap();
+        ron(&new);
+        let entries = entries(Some(&new), List::Admins).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].detail.as_deref(), Some("Moderator"));
+    }
+
+    #[test]
+    fn add_refuses_other_versions() {
+        let content = ADMINS.replace("V1(", "V2(");
+        let e = add(Some(&content), BOB, "bob", &Record::Whitelist, now()).unwrap_err();
+        assert_eq!(e, "The file has format V2, only V1 can be edited.");
+    }
+
+    #[test]
+    fn ban_keeps_history() {
+        let ban = |reason: &str| Record::Ban {
+            reason: reason.to_string(),
+            until: None,
+        };
+        let first = add(None, ALICE, "alice", &ban("first"), now()).unwrap();
+        let second = add(Some(&first), ALICE, "alice", &ban("second"), now()).unwrap();
+        let third = add(Some(&second), ALICE, "alice", &ban("third"), now()).unwrap();
+        ron(&third);
+
+        let root = parse(&third).unwrap();
+        let (_, players) = players(&root).unwrap();
+        assert_eq!(players.len(), 1);
+        let history = match &players[0].1.field("history").unwrap().value {
+            Value::Tuple(None, history) => history,
+            value => panic!("{:?}", value),
+        };
+        let reasons: Vec<_> = history
+            .iter()
+            .map(|record| &third[record.span.clone()])
+            .collect();
+        assert_eq!(reasons.len(), 2);
+        assert!(reasons[0].contains("\"first\""));
+        assert!(reasons[1].contains("\"second\""));
+
+        let entries = entries(Some(&third), List::Banlist).unwrap();
+        assert_eq!(entries[0].detail.as_deref(), Some("third"));
+    }
+
+    #[test]
+    fn ban_until() {
+        let until = Utc.with_ymd_and_hms(2024, 2, 2, 12, 0, 0).unwrap();
+        let record = Record::Ban {
+            reason: "griefing \"spawn\"".to_string(),
+            until: Some(until),
+        };
+        let new = add(None, ALICE, "alice", &record, now()).unwrap();
+        ron(&new);
+        let entries = entries(Some(&new), List::Banlist).unwrap();
+        assert_eq!(
+            entries[0].detail.as_deref(),
+            Some("griefing \"spawn\" (until 2024-02-02T12:00:00Z)")
+        );
+    }
+
+    #[test]
+    fn lifted_bans_are_left_out() {
+        let content = format!(
+            r#"V1({{
+    "{}": (
+        current: (username_when_performed: "alice", action: Unban, date: "2024-01-31T04:00:00Z"),
+        history: [],
+    ),
+}})"#,
+            ALICE
+        );
+        assert!(entries(Some(&content), List::Banlist).unwrap().is_empty());
+        assert!(matches!(
+            remove(Some(&content), List::Banlist, ALICE),
+            Ok(None)
+        ));
+    }
+
+    #[test]
+    fn raw_strings() {
+        let content = format!(
+            r###"V1({{
+    "{}": (
+        current: (
+            username_when_performed: r"alice",
+            action: Ban((reason: r#"said "hi" \o/"#, info: None, end_date: None)),
+            date: "2024-01-31T04:00:00Z",
+        ),
+        history: [],
+    ),
+}})"###,
+            ALICE
+        );
+        ron(&content);
+        let entries = entries(Some(&content), List::Banlist).unwrap();
+        assert_eq!(entries[0].username.as_deref(), Some("alice"));
+        assert_eq!(entries[0].detail.as_deref(), Some(r#"said "hi" \o/"#));
+
+        let (new, _) = remove(Some(&content), List::Banlist, "alice")
+            .unwrap()
+            .unwrap();
+        assert_eq!(new, "V1({\n})");
+    }
+
+    #[test]
+    fn escaped_strings() {
+        let content = format!(
+            r#"V1({{
+    "{}": (
+        username_when_admined: Some("z\u{{e9}}\"ro\\"),
+        date: "2024-01-31T04:00:00Z",
+        role: Admin,
+    ),
+}})"#,
+            ALICE
+        );
+        ron(&content);
+        assert_eq!(
+            usernames(&content, List::Admins),
+            [Some("z\u{e9}\"ro\\".to_string())]
+        );
+        assert!(remove(Some(&content), List::Admins, "z\u{e9}\"ro\\")
+            .unwrap()
+            .is_some());
+    }
+
+    #[test]
+    fn whitelist_records_username() {
+        let content = add(None, ALICE, "alice", &Record::Whitelist, now()).unwrap();
+        let content = add(Some(&content), BOB, "bob", &Record::Whitelist, now()).unwrap();
+        ron(&content);
+        assert_eq!(
+            usernames(&content, List::Whitelist),
+            [Some("alice".to_string()), Some("bob".to_string())]
+        );
+        let (new, entry) = remove(Some(&content), List::Whitelist, "bob")
+            .unwrap()
+            .unwrap();
+        assert_eq!(entry.uuid, BOB);
+        assert_eq!(usernames(&new, List::Whitelist).len(), 1);
+    }
+
+    #[test]
+    fn remove_single_entry() {
+        let (new, entry) = remove(Some(ADMINS), List::Admins, ALICE).unwrap().unwrap();
+        ron(&new);
+        assert_eq!(new, "V1({\n})\n");
+        assert_eq!(entry.username.as_deref(), Some("alice"));
+    }
+
+    #[test]
+    fn remove_without_file() {
+        assert!(matches!(remove(None, List::Admins, ALICE), Ok(None)));
+        assert!(matches!(
+            remove(Some("V1({})"), List::Admins, ALICE),
+            Ok(None)
+        ));
+    }
+
+    #[test]
+    fn remove_by_username() {
+        let role = Record::Admin(AdminRole::Moderator);
+        let content = add(Some(ADMINS), BOB, "bob", &role, now()).unwrap();
+        let (new, entry) = remove(Some(&content), List::Admins, "ALICE")
+            .unwrap()
+            .unwrap();
+        ron(&new);
+        assert_eq!(entry.uuid, ALICE);
+        assert_eq!(usernames(&new, List::Admins), [Some("bob".to_string())]);
+    }
+
+    #[test]
+    fn remove_last_without_trailing_comma() {
+        let content = format!(
+            r#"V1({{
+    // Regulars
+    "{}": (date: "2024-01-31T04:00:00Z", info: None),
+    "{}": (date: "2024-01-31T04:00:00Z", info: None)
+}})"#,
+            ALICE, BOB
+        );
+        let (new, _) = remove(Some(&content), List::Whitelist, BOB)
+            .unwrap()
+            .unwrap();
+        ron(&new);
+        assert!(new.contains("// Regulars"));
+        assert_eq!(usernames(&new, List::Whitelist), [None]);
+    }
+
+    #[test]
+    fn remove_inline_entry() {
+        let content = format!(
+            "V1({{\"{}\": (date: \"2024-01-31T04:00:00Z\", info: None), \
+            \"{}\": (date: \"2024-01-31T04:00:00Z\", info: None)}})",
+            ALICE, BOB
+        );
+        let (new, _) = remove(Some(&content), List::Whitelist, ALICE)
+            .unwrap()
+            .unwrap();
+        ron(&new);
+        assert_eq!(entries(Some(&new), List::Whitelist).unwrap()[0].uuid, BOB);
+    }
+
+    #[test]
+    fn invalid_files_are_refused() {
+        let e = entries(Some("V1({\n    \"a\": (date: \"x\"\n})"), List::Whitelist).unwrap_err();
+        assert_eq!(e, "Expected `,` or `)` at line 3, column 1.");
+        let e = add(
+            Some("V1({\"a\": \"unclosed})"),
+            ALICE,
+            "alice",
+            &Record::Whitelist,
+            now(),
+        )
+        .unwrap_err();
+        assert_eq!(e, "Unclosed string at line 1, column 10.");
+    }
+
+    #[test]
+    fn broken_edits_are_refused() {
+        assert!(checked("V1({})".to_string()).is_ok());
+        assert_eq!(
+            checked("V1({}),\n    \"a\": ()".to_string()),
+            Err("Unexpected content after the value at line 1, column 7.".to_string())
+        );
+    }
+}
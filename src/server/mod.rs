@@ -1,19 +1,172 @@
+mod build;
+pub mod editable;
 mod task;
+mod workspace;
 
-use crate::{state::Rev, utils};
+use crate::{
+    backup::{self, Backup, Retention},
+    state::{Profile, RestartPolicy, Rev, State},
+    utils,
+};
 use anyhow::{Context, Result};
-use futures::FutureExt;
+pub use build::{log_path as build_log_path, Diagnostic, Progress};
+use build::{BuildLog, BINARY};
+use editable::AdminRole;
 use linked_hash_set::LinkedHashSet;
-use std::{collections::HashMap, path::PathBuf};
+use std::{
+    collections::HashMap,
+    future::Future,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
 use task::Task;
-use tokio::{process::Command, sync::mpsc};
+use tokio::{
+    process::Command,
+    sync::{broadcast, mpsc, oneshot},
+    time::Instant,
+};
+pub use workspace::Workspace;
+
+/// How long to wait for the first line of output after executing a command.
+const EXEC_RESPONSE_TIMEOUT: Duration = Duration::from_secs(3);
+/// How long the gameserver has to stay quiet until its response is considered complete.
+const EXEC_QUIET_TIMEOUT: Duration = Duration::from_millis(500);
+/// Upper bound on how long output of a single command is collected.
+const EXEC_MAX_DURATION: Duration = Duration::from_secs(10);
+/// server-cli command which saves and shuts down the gameserver.
+const SHUTDOWN_COMMAND: &str = "shutdown immediate";
+/// server-cli command which shows a message to all players online.
+const GLOBAL_MESSAGE_COMMAND: &str = "send-global-msg";
+/// server-cli command which lists the names of the players online.
+const LIST_PLAYERS_COMMAND: &str = "list-players";
+/// server-cli command which makes a player an admin, followed by their name and role.
+const ADD_ADMIN_COMMAND: &str = "admin add";
+/// server-cli command which takes the role of an admin, followed by their name.
+const REMOVE_ADMIN_COMMAND: &str = "admin remove";
+/// Delay before the first restart after the gameserver exited. Doubles with every further attempt.
+const RESTART_BACKOFF: Duration = Duration::from_secs(5);
+/// Upper bound for the delay between restarts.
+const RESTART_BACKOFF_MAX: Duration = Duration::from_secs(5 * 60);
+/// Uptime after which the gameserver is considered stable again and the retries are reset.
+const STABLE_UPTIME: Duration = Duration::from_secs(10 * 60);
 
 #[derive(Debug)]
 pub struct Server {
-    reporter: Option<mpsc::UnboundedReceiver<ServerStatus>>,
+    reporter: mpsc::UnboundedSender<ServerStatus>,
+    report: Arc<Mutex<Report>>,
+    transitions: broadcast::Sender<Transition>,
     task: Option<Task>,
+    console: Option<Console>,
+    /// Build of a new rev next to the running gameserver.
+    staging: Option<Task>,
+    /// Number of stagings started, to tell if a finished one is still the current one.
+    stagings: u64,
+    /// Checkout the gameserver is built from.
+    checkout: PathBuf,
+    /// What the running gameserver has been started with.
+    started_with: Option<Snapshot>,
+    /// Cargo arguments of the build started last, which decide where its userdata is.
+    /// Unlike [`Self::started_with`] kept after it stopped.
+    built_with: Option<LinkedHashSet<String>>,
+    paths: Paths,
+    /// Time the gameserver gets to shut down before it is killed.
+    grace_period: Duration,
+    /// How many backups are kept.
+    retention: Retention,
+}
+
+/// Rev and configuration the gameserver is started with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snapshot {
+    pub rev: Rev,
+    pub profile: Profile,
+}
+
+impl Snapshot {
+    pub fn new(
+        rev: &Rev,
+        args: &LinkedHashSet<String>,
+        cargo_args: &LinkedHashSet<String>,
+        envs: &HashMap<String, String>,
+    ) -> Self {
+        Self {
+            rev: rev.clone(),
+            profile: Profile {
+                cargo: cargo_args.clone(),
+                envs: envs.clone(),
+                args: args.clone(),
+            },
+        }
+    }
+
+    /// What the gameserver would be started with next.
+    pub fn of(state: &State) -> Self {
+        Self {
+            rev: state.rev().clone(),
+            profile: Profile {
+                envs: state.envs(),
+                ..state.current_profile()
+            },
+        }
+    }
+}
+
+/// Everything the server tasks reported so far.
+#[derive(Debug)]
+struct Report {
     status: ServerStatus,
+    /// Rev the server has been started with.
+    rev: Option<Rev>,
     version: Option<String>,
+    /// How often the gameserver crashed since it has been started.
+    crashes: u32,
+    /// Exit code of the last time the gameserver exited on its own.
+    exit_code: Option<i32>,
+    /// Errors of the last failed build.
+    diagnostics: Vec<Diagnostic>,
+    /// Progress of the current or last build.
+    progress: Option<Progress>,
+    staging: Option<Staging>,
+}
+
+/// Rev which is built next to the running gameserver.
+#[derive(Debug)]
+struct Staging {
+    rev: Rev,
+    version: Option<String>,
+    /// `None` until the build reported anything.
+    status: Option<ServerStatus>,
+}
+
+/// A change of the [`ServerStatus`].
+#[derive(Debug, Clone)]
+pub struct Transition {
+    pub from: ServerStatus,
+    pub to: ServerStatus,
+    pub rev: Option<Rev>,
+    pub version: Option<String>,
+    pub diagnostics: Vec<Diagnostic>,
+    pub progress: Option<Progress>,
+}
+
+/// Connection to the stdin and output of the gameserver process.
+#[derive(Debug)]
+struct Console {
+    input: mpsc::UnboundedSender<String>,
+    output: broadcast::Sender<String>,
+    /// Set when the gameserver is asked to shut down, so it won't be restarted.
+    stopping: Arc<AtomicBool>,
+}
+
+/// Gameserver end of the [`Console`].
+struct Pipes {
+    input: mpsc::UnboundedReceiver<String>,
+    output: broadcast::Sender<String>,
+    stopping: Arc<AtomicBool>,
 }
 
 #[derive(Debug, Clone, Ord, PartialOrd, Eq, PartialEq)]
@@ -23,12 +176,31 @@ pub enum ServerStatus {
     Version(String),
     Compiling,
     Online,
+    Stopping,
+    Restarting,
+    Exited(Option<i32>),
+    Diagnostics(Vec<Diagnostic>),
+    Progress(Progress),
+    /// Status of the build next to the running gameserver.
+    Staging(Box<ServerStatus>),
+    /// Staged build is done and about to replace the running gameserver.
+    Ready,
 
     UpdateFailed,
     CompileFailed,
     RunFailed,
 }
 
+impl ServerStatus {
+    /// The status itself, no matter if it belongs to a staging.
+    pub fn unstaged(&self) -> &Self {
+        match self {
+            Self::Staging(status) => status.unstaged(),
+            status => status,
+        }
+    }
+}
+
 impl std::fmt::Display for ServerStatus {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match &self {
@@ -36,30 +208,150 @@ impl std::fmt::Display for ServerStatus {
             ServerStatus::Updating => write!(f, "Updating..."),
             ServerStatus::Compiling => write!(f, "Compiling..."),
             ServerStatus::Online => write!(f, "Online"),
+            ServerStatus::Stopping => write!(f, "Stopping..."),
+            ServerStatus::Restarting => write!(f, "Restarting..."),
             ServerStatus::UpdateFailed => write!(f, "Failed to update"),
             ServerStatus::CompileFailed => write!(f, "Compile Failed"),
             ServerStatus::RunFailed => write!(f, "Starting Failed"),
+            ServerStatus::Staging(status) => write!(f, "Staging: {}", status),
+            ServerStatus::Ready => write!(f, "Ready to switch"),
             ServerStatus::Version(_) => {
                 unreachable!("ServerStatus::Version should be catched by Server!")
             }
+            ServerStatus::Exited(_) => {
+                unreachable!("ServerStatus::Exited should be catched by Server!")
+            }
+            ServerStatus::Diagnostics(_) => {
+                unreachable!("ServerStatus::Diagnostics should be catched by Server!")
+            }
+            ServerStatus::Progress(_) => {
+                unreachable!("ServerStatus::Progress should be catched by Server!")
+            }
+        }
+    }
+}
+
+/// Where a gameserver lives on disk.
+#[derive(Debug, Clone)]
+pub struct Paths {
+    /// Clone of the repository, which owns the target dir shared with the staging worktree.
+    /// Each checkout runs its own copy of the gameserver binary, see [`Self::deployed`].
+    pub home: PathBuf,
+    /// Directory the gameserver keeps its saves and configs in.
+    /// Defaults to the one next to the gameserver binary.
+    pub userdata: Option<PathBuf>,
+    /// Where the log of the last build is saved.
+    pub build_log: PathBuf,
+    /// Where backups of the userdata are kept.
+    pub backups: PathBuf,
+}
+
+impl Paths {
+    /// Worktree of the repository to build new revs in while the gameserver keeps running.
+    fn staging(&self) -> PathBuf {
+        let mut staging = self.home.clone().into_os_string();
+        staging.push("-staging");
+        PathBuf::from(staging)
+    }
+
+    /// Target dir shared by all checkouts, so staged builds only recompile what changed.
+    fn target(&self) -> PathBuf {
+        let target = self.home.join("target");
+        std::path::absolute(&target).unwrap_or(target)
+    }
+
+    /// Where the gameserver binary built in `checkout` is run from.
+    /// A copy, so builds in the shared target dir can't replace the binary of the running gameserver.
+    fn deployed(&self, checkout: &Path) -> PathBuf {
+        let name = checkout.file_name().unwrap_or(checkout.as_os_str());
+        self.target().join("deployed").join(name).join(BINARY)
+    }
+
+    /// Directory the gameserver keeps its saves and configs in when built with `cargo_args`.
+    pub fn userdata(&self, cargo_args: &LinkedHashSet<String>) -> PathBuf {
+        match &self.userdata {
+            Some(userdata) => std::path::absolute(userdata).unwrap_or_else(|_| userdata.clone()),
+            None => self.target().join(profile_dir(cargo_args)).join("userdata"),
+        }
+    }
+}
+
+/// Directory in the target dir cargo puts the builds of the profile selected by `cargo_args`.
+fn profile_dir(cargo_args: &LinkedHashSet<String>) -> String {
+    let mut profile = "dev".to_string();
+    let mut args = cargo_args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--release" | "-r" => profile = "release".to_string(),
+            "--profile" => {
+                if let Some(name) = args.next() {
+                    profile = name.clone();
+                }
+            }
+            arg => {
+                if let Some(name) = arg.strip_prefix("--profile=") {
+                    profile = name.to_string();
+                }
+            }
         }
     }
+
+    match profile.as_str() {
+        "dev" | "test" => "debug".to_string(),
+        "bench" => "release".to_string(),
+        _ => profile,
+    }
+}
+
+/// Delay before restarting the gameserver after it already has been restarted `retries` times.
+fn restart_backoff(retries: u32) -> Duration {
+    RESTART_BACKOFF
+        .saturating_mul(2u32.saturating_pow(retries))
+        .min(RESTART_BACKOFF_MAX)
 }
 
 impl Server {
-    pub async fn new(repo: impl ToString) -> Result<Self> {
+    pub async fn new(
+        repo: impl ToString,
+        paths: Paths,
+        grace_period: Duration,
+        retention: Retention,
+    ) -> Result<Self> {
         // First setup
-        if !PathBuf::from("veloren/Cargo.toml").exists() {
-            Self::clone_repository(repo)
+        if !paths.home.join("Cargo.toml").exists() {
+            Self::clone_repository(repo, &paths.home)
                 .await
                 .context("Failed to clone repository for the first time.")?;
         }
 
-        Ok(Self {
-            reporter: None,
-            task: None,
+        let (reporter, recv) = mpsc::unbounded_channel();
+        let (transitions, _) = broadcast::channel(64);
+        let report = Arc::new(Mutex::new(Report {
             status: ServerStatus::Offline,
+            rev: None,
             version: None,
+            crashes: 0,
+            exit_code: None,
+            diagnostics: Vec::new(),
+            progress: None,
+            staging: None,
+        }));
+        tokio::spawn(Self::monitor(recv, report.clone(), transitions.clone()));
+
+        Ok(Self {
+            reporter,
+            report,
+            transitions,
+            task: None,
+            console: None,
+            staging: None,
+            stagings: 0,
+            checkout: paths.home.clone(),
+            started_with: None,
+            built_with: None,
+            paths,
+            grace_period,
+            retention,
         })
     }
 
@@ -70,18 +362,58 @@ impl Server {
         args: &LinkedHashSet<String>,
         cargo_args: &LinkedHashSet<String>,
         envs: &HashMap<String, String>,
+        policy: RestartPolicy,
     ) -> bool {
-        self.run(rev, args, cargo_args, envs).await
+        self.run(rev, args, cargo_args, envs, policy).await
     }
 
+    /// Asks a running gameserver to shut down and waits up to the grace period for it to do so.
+    /// Anything still running afterwards (including updating and compiling) gets killed.
     pub async fn stop(&mut self) -> bool {
-        if let Some(task) = self.task.take() {
+        if let Some(staging) = self.staging.take() {
+            staging.cancel().await;
+            self.report.lock().unwrap().staging = None;
+        }
+
+        let task = match self.task.take() {
+            Some(task) => task,
+            None => return false,
+        };
+
+        if let Err(task) = self.shutdown(task).await {
             task.cancel().await;
-            self.status = ServerStatus::Offline;
-            true
-        } else {
-            false
         }
+        self.console = None;
+        self.started_with = None;
+        let _ = self.reporter.send(ServerStatus::Offline);
+        true
+    }
+
+    /// Sends the shutdown command to an online gameserver and waits for it to exit.
+    ///
+    /// Hands the task back if the gameserver isn't online or did not exit in time.
+    async fn shutdown(&mut self, task: Task) -> Result<(), Task> {
+        let console = match self.console.take() {
+            Some(console) => console,
+            None => return Err(task),
+        };
+        if self.status() != ServerStatus::Online {
+            return Err(task);
+        }
+        console.stopping.store(true, Ordering::Relaxed);
+        if console.input.send(SHUTDOWN_COMMAND.to_string()).is_err() {
+            return Err(task);
+        }
+
+        let _ = self.reporter.send(ServerStatus::Stopping);
+        log::info!("Shutting down Veloren Server...");
+
+        task.wait(self.grace_period).await.inspect_err(|_| {
+            log::warn!(
+                "Veloren Server did not shut down within {:?}. Killing it.",
+                self.grace_period
+            )
+        })
     }
 
     pub async fn restart(
@@ -90,27 +422,267 @@ impl Server {
         args: &LinkedHashSet<String>,
         cargo_args: &LinkedHashSet<String>,
         envs: &HashMap<String, String>,
+        policy: RestartPolicy,
     ) {
         self.stop().await;
-        self.run(rev, args, cargo_args, envs).await;
+        self.run(rev, args, cargo_args, envs, policy).await;
+    }
+
+    pub fn status(&self) -> ServerStatus {
+        self.report.lock().unwrap().status.clone()
+    }
+
+    pub fn version(&self) -> Option<String> {
+        self.report.lock().unwrap().version.clone()
     }
 
-    pub async fn status(&mut self) -> ServerStatus {
-        if let Some(reporter) = &mut self.reporter {
-            // TODO: https://github.com/tokio-rs/tokio/pull/3263
-            while let Some(status) = reporter.recv().now_or_never().flatten() {
-                match status {
-                    ServerStatus::Version(version) => self.version = Some(version),
-                    status => self.status = status,
+    /// How often the gameserver crashed since it has been started.
+    pub fn crashes(&self) -> u32 {
+        self.report.lock().unwrap().crashes
+    }
+
+    /// Exit code of the last time the gameserver exited on its own.
+    /// `None` if it never did or got killed by a signal.
+    pub fn exit_code(&self) -> Option<i32> {
+        self.report.lock().unwrap().exit_code
+    }
+
+    /// Errors of the last failed build.
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        self.report.lock().unwrap().diagnostics.clone()
+    }
+
+    /// Progress of the current or last build.
+    pub fn progress(&self) -> Option<Progress> {
+        self.report.lock().unwrap().progress
+    }
+
+    /// Rev which is currently built next to the running gameserver and how far it got.
+    pub fn staging(&self) -> Option<(Rev, Option<ServerStatus>)> {
+        let report = self.report.lock().unwrap();
+        let staging = report.staging.as_ref()?;
+        Some((staging.rev.clone(), staging.status.clone()))
+    }
+
+    /// Builds `rev` next to the running gameserver and only replaces it once the build succeeded.
+    /// A failed build leaves the running gameserver untouched.
+    ///
+    /// Falls back to a regular restart if the gameserver isn't online.
+    /// `server` has to be the shared handle to `self`, which is locked again for the switch.
+    pub async fn switch(
+        &mut self,
+        server: &Arc<tokio::sync::Mutex<Self>>,
+        rev: &Rev,
+        args: &LinkedHashSet<String>,
+        cargo_args: &LinkedHashSet<String>,
+        envs: &HashMap<String, String>,
+        policy: RestartPolicy,
+    ) {
+        if self.status() != ServerStatus::Online {
+            self.restart(rev, args, cargo_args, envs, policy).await;
+            return;
+        }
+        let staged = self.stage(rev, cargo_args).await;
+        let staging = self.stagings;
+
+        let server = server.clone();
+        let userdata = self.paths.userdata(cargo_args);
+        let snapshot = Snapshot::new(rev, args, cargo_args, envs);
+        tokio::spawn(async move {
+            match staged.await {
+                Ok(Some((checkout, executable))) => {
+                    server
+                        .lock()
+                        .await
+                        .promote(checkout, executable, userdata, snapshot, policy)
+                        .await;
                 }
+                Ok(None) => server.lock().await.unstage(staging),
+                // Gets dropped if the staging has been cancelled.
+                Err(_) => {}
             }
+        });
+    }
+
+    /// Forgets about the failed `staging` unless another one has been started since.
+    fn unstage(&mut self, staging: u64) {
+        if self.stagings == staging {
+            self.staging = None;
         }
+    }
 
-        self.status.clone()
+    /// Brings the gameserver to the rev of `state`, keeping it online meanwhile if enabled.
+    /// `server` has to be the shared handle to `self`.
+    pub async fn deploy(&mut self, server: &Arc<tokio::sync::Mutex<Self>>, state: &State) {
+        if let Err(e) = self.backup(state.cargo_args(), "deploy").await {
+            log::error!("Failed to back up userdata before deploying: {:?}", e);
+        }
+
+        match state.seamless() {
+            true => {
+                self.switch(
+                    server,
+                    state.rev(),
+                    state.args(),
+                    state.cargo_args(),
+                    &state.envs(),
+                    state.restart_policy(),
+                )
+                .await
+            }
+            false => {
+                self.restart(
+                    state.rev(),
+                    state.args(),
+                    state.cargo_args(),
+                    &state.envs(),
+                    state.restart_policy(),
+                )
+                .await
+            }
+        }
     }
 
-    pub fn version(&self) -> Option<String> {
-        self.version.clone()
+    /// Shows `message` to all players online. Resolves to `false` if the gameserver isn't online.
+    /// Like [`Self::exec`], the server can be unlocked before awaiting.
+    pub fn broadcast(&self, message: &str) -> impl Future<Output = bool> {
+        let command = format!(
+            "{} \"{}\"",
+            GLOBAL_MESSAGE_COMMAND,
+            message.replace('"', "'")
+        );
+        let response = self.exec(&command);
+        async move { response.await.is_some() }
+    }
+
+    /// Asks the gameserver how many players are online.
+    /// Like [`Self::exec`], the server can be unlocked before awaiting.
+    ///
+    /// Resolves to `None` if the gameserver isn't online or didn't tell.
+    pub fn players_online(&self) -> impl Future<Output = Option<usize>> {
+        let response = self.exec(LIST_PLAYERS_COMMAND);
+        async move { Self::count_players(&response.await?) }
+    }
+
+    /// Number of players in the response to [`LIST_PLAYERS_COMMAND`].
+    fn count_players(lines: &[String]) -> Option<usize> {
+        lines.iter().find_map(|line| {
+            // Responds with e.g. `Players online: ["a", "b"]`.
+            let (_, players) = line.split_once("Players online: ")?;
+            let players = &players[players.find('[')? + 1..players.rfind(']')?];
+            Some(match players.trim() {
+                "" => 0,
+                players => players.split(", ").count(),
+            })
+        })
+    }
+
+    /// Backs up the userdata, see [`Self::userdata`], if there is any yet.
+    /// Deletes the backups the retention doesn't keep afterwards.
+    pub async fn backup(
+        &self,
+        cargo_args: &LinkedHashSet<String>,
+        reason: &str,
+    ) -> Result<Option<Backup>> {
+        self.backup_sparing(cargo_args, reason, None).await
+    }
+
+    /// Like [`Self::backup`], but keeps `spare` even if the retention doesn't.
+    pub async fn backup_sparing(
+        &self,
+        cargo_args: &LinkedHashSet<String>,
+        reason: &str,
+        spare: Option<&Backup>,
+    ) -> Result<Option<Backup>> {
+        let userdata = self.userdata(cargo_args);
+        if !userdata.join("server").exists() {
+            return Ok(None);
+        }
+        let created = backup::create(&userdata, &self.paths.backups, reason).await?;
+        if let Err(e) = backup::prune(&self.paths.backups, self.retention, spare).await {
+            log::error!("Failed to prune backups: {:?}", e);
+        }
+        Ok(Some(created))
+    }
+
+    /// How many backups are kept.
+    pub fn retention(&self) -> Retention {
+        self.retention
+    }
+
+    /// Makes the player called `username` an admin with `role`, see [`Self::exec`].
+    pub fn add_admin(
+        &self,
+        username: &str,
+        role: AdminRole,
+    ) -> impl Future<Output = Option<Vec<String>>> {
+        let role = role.to_string().to_lowercase();
+        self.exec(&format!("{} {} {}", ADD_ADMIN_COMMAND, username, role))
+    }
+
+    /// Takes the role of the admin called `username`, see [`Self::exec`].
+    pub fn remove_admin(&self, username: &str) -> impl Future<Output = Option<Vec<String>>> {
+        self.exec(&format!("{} {}", REMOVE_ADMIN_COMMAND, username))
+    }
+
+    /// Directory the last started build keeps its saves and configs in.
+    /// The one of `cargo_args` if none has been started yet.
+    pub fn userdata(&self, cargo_args: &LinkedHashSet<String>) -> PathBuf {
+        self.paths
+            .userdata(self.built_with.as_ref().unwrap_or(cargo_args))
+    }
+
+    /// Where the gameserver lives on disk.
+    pub fn paths(&self) -> &Paths {
+        &self.paths
+    }
+
+    /// What the gameserver has been started with, if it's running.
+    pub fn started_with(&self) -> Option<&Snapshot> {
+        self.started_with.as_ref()
+    }
+
+    /// Get notified about every change of the [`ServerStatus`].
+    pub fn subscribe(&self) -> broadcast::Receiver<Transition> {
+        self.transitions.subscribe()
+    }
+
+    /// Writes `command` to the stdin of the gameserver and collects the output it responds with.
+    /// Collecting takes a while, so unlock the server before awaiting it.
+    ///
+    /// Resolves to `None` if the gameserver isn't online.
+    pub fn exec(&self, command: &str) -> impl Future<Output = Option<Vec<String>>> {
+        // Subscribe before sending to not miss any response.
+        let output = self
+            .console
+            .as_ref()
+            .filter(|_| self.status() == ServerStatus::Online)
+            .and_then(|console| {
+                let output = console.output.subscribe();
+                console.input.send(command.to_string()).ok()?;
+                Some(output)
+            });
+        async move { Some(Self::collect(output?).await) }
+    }
+
+    /// Output of the gameserver until it went quiet.
+    async fn collect(mut output: broadcast::Receiver<String>) -> Vec<String> {
+        let deadline = Instant::now() + EXEC_MAX_DURATION;
+        let mut timeout = EXEC_RESPONSE_TIMEOUT;
+        let mut lines = Vec::new();
+        loop {
+            let until = std::cmp::min(Instant::now() + timeout, deadline);
+            match tokio::time::timeout_at(until, output.recv()).await {
+                Ok(Ok(line)) => lines.push(line),
+                Ok(Err(broadcast::error::RecvError::Lagged(skipped))) => {
+                    lines.push(format!("... skipped {} lines ...", skipped))
+                }
+                Ok(Err(broadcast::error::RecvError::Closed)) | Err(_) => break,
+            }
+            timeout = EXEC_QUIET_TIMEOUT;
+        }
+
+        lines
     }
 
     pub async fn clean(
@@ -119,14 +691,20 @@ impl Server {
         args: &LinkedHashSet<String>,
         cargo_args: &LinkedHashSet<String>,
         envs: &HashMap<String, String>,
+        policy: RestartPolicy,
     ) -> bool {
         // Stop server
         self.stop().await;
 
+        // The userdata lives in the target dir by default.
+        if let Err(e) = self.backup(cargo_args, "prune").await {
+            log::error!("Failed to back up userdata before cleaning: {:?}", e);
+            return false;
+        }
+
         // Clean
         log::info!("Cleaning...");
-        let mut cmd = Command::new("cargo");
-        cmd.current_dir(PathBuf::from("veloren"));
+        let mut cmd = Self::cargo(&self.paths, &self.checkout);
         cmd.arg("clean");
 
         if let Err(e) = utils::execute("cargo", cmd).await {
@@ -135,7 +713,7 @@ impl Server {
         }
 
         // Start
-        self.run(rev, args, cargo_args, envs).await;
+        self.run(rev, args, cargo_args, envs, policy).await;
         true
     }
 
@@ -145,42 +723,334 @@ impl Server {
         args: &LinkedHashSet<String>,
         cargo_args: &LinkedHashSet<String>,
         envs: &HashMap<String, String>,
+        policy: RestartPolicy,
     ) -> bool {
         if self.task.is_none() {
-            let (send, recv) = mpsc::unbounded_channel();
-            self.reporter = Some(recv);
-            self.task = Some(Task::new(Self::setup(
-                send,
-                rev.clone(),
-                args.clone(),
-                cargo_args.clone(),
-                envs.clone(),
-            )));
+            let (paths, checkout) = (self.paths.clone(), self.checkout.clone());
+            let (rev, args, cargo_args, envs) =
+                (rev.clone(), args.clone(), cargo_args.clone(), envs.clone());
+            self.started_with = Some(Snapshot::new(&rev, &args, &cargo_args, &envs));
+            self.built_with = Some(cargo_args.clone());
+            self.spawn(&rev.clone(), None, move |reporter, pipes| {
+                Self::setup(
+                    reporter, pipes, paths, checkout, rev, args, cargo_args, envs, policy,
+                )
+            });
             true
         } else {
             false
         }
     }
 
+    /// Runs `pipeline` as the server task, connected to a fresh console.
+    /// `version` is the commit of `rev` if it's known already.
+    fn spawn<P, F>(&mut self, rev: &Rev, version: Option<String>, pipeline: P)
+    where
+        P: FnOnce(mpsc::UnboundedSender<ServerStatus>, Pipes) -> F,
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let (input, input_recv) = mpsc::unbounded_channel();
+        let (output, _) = broadcast::channel(256);
+        let stopping = Arc::new(AtomicBool::new(false));
+        {
+            let mut report = self.report.lock().unwrap();
+            report.rev = Some(rev.clone());
+            report.version = version;
+            report.crashes = 0;
+            report.diagnostics.clear();
+            report.progress = None;
+        }
+        self.task = Some(Task::new(pipeline(
+            self.reporter.clone(),
+            Pipes {
+                input: input_recv,
+                output: output.clone(),
+                stopping: stopping.clone(),
+            },
+        )));
+        self.console = Some(Console {
+            input,
+            output,
+            stopping,
+        });
+    }
+
+    /// Builds `rev` in the checkout the gameserver doesn't run from.
+    ///
+    /// Resolves to the checkout and gameserver binary once the build succeeded,
+    /// or to `None` if it failed.
+    async fn stage(
+        &mut self,
+        rev: &Rev,
+        cargo_args: &LinkedHashSet<String>,
+    ) -> oneshot::Receiver<Option<(PathBuf, PathBuf)>> {
+        if let Some(staging) = self.staging.take() {
+            staging.cancel().await;
+        }
+        self.stagings += 1;
+
+        let checkout = match self.checkout == self.paths.home {
+            true => self.paths.staging(),
+            false => self.paths.home.clone(),
+        };
+        {
+            let mut report = self.report.lock().unwrap();
+            report.staging = Some(Staging {
+                rev: rev.clone(),
+                version: None,
+                status: None,
+            });
+            report.diagnostics.clear();
+            report.progress = None;
+        }
+
+        let (done, staged) = oneshot::channel();
+        self.staging = Some(Task::new(Self::setup_staging(
+            self.reporter.clone(),
+            self.paths.clone(),
+            checkout,
+            rev.clone(),
+            cargo_args.clone(),
+            done,
+        )));
+        staged
+    }
+
+    /// Replaces the running gameserver with a staged build of `snapshot`.
+    async fn promote(
+        &mut self,
+        checkout: PathBuf,
+        executable: PathBuf,
+        userdata: PathBuf,
+        snapshot: Snapshot,
+        policy: RestartPolicy,
+    ) {
+        // The staging is done, don't cancel it.
+        self.staging = None;
+        let version = self
+            .report
+            .lock()
+            .unwrap()
+            .staging
+            .take()
+            .and_then(|staging| staging.version);
+
+        self.stop().await;
+        log::info!(
+            "Switching to {} built in {}...",
+            snapshot.rev,
+            checkout.display()
+        );
+        self.checkout = checkout.clone();
+
+        let Snapshot { rev, profile } = snapshot.clone();
+        self.started_with = Some(snapshot);
+        self.built_with = Some(profile.cargo.clone());
+        self.spawn(&rev, version, move |reporter, mut pipes| async move {
+            let mut reporter = Some(reporter);
+            Self::run_server(
+                &mut reporter,
+                &mut pipes,
+                &userdata,
+                &checkout,
+                &executable,
+                &profile.args,
+                &profile.envs,
+                policy,
+            )
+            .await;
+        });
+    }
+
+    /// Applies the reports of the server tasks and announces status changes.
+    async fn monitor(
+        mut reporter: mpsc::UnboundedReceiver<ServerStatus>,
+        report: Arc<Mutex<Report>>,
+        transitions: broadcast::Sender<Transition>,
+    ) {
+        while let Some(status) = reporter.recv().await {
+            let mut report = report.lock().unwrap();
+            let report = &mut *report;
+            match status {
+                ServerStatus::Version(version) => report.version = Some(version),
+                ServerStatus::Diagnostics(diagnostics) => report.diagnostics = diagnostics,
+                ServerStatus::Progress(progress) => report.progress = Some(progress),
+                ServerStatus::Exited(code) => {
+                    report.exit_code = code;
+                    if code != Some(0) {
+                        report.crashes += 1;
+                    }
+                }
+                ServerStatus::Staging(status) => {
+                    // Reports of a cancelled staging may still be in flight.
+                    let staging = match &mut report.staging {
+                        Some(staging) => staging,
+                        None => continue,
+                    };
+                    match *status {
+                        ServerStatus::Version(version) => staging.version = Some(version),
+                        ServerStatus::Diagnostics(diagnostics) => report.diagnostics = diagnostics,
+                        ServerStatus::Progress(progress) => report.progress = Some(progress),
+                        status if Some(&status) != staging.status.as_ref() => {
+                            let from = match staging.status.replace(status.clone()) {
+                                Some(from) => ServerStatus::Staging(Box::new(from)),
+                                None => report.status.clone(),
+                            };
+                            let failed = matches!(
+                                status,
+                                ServerStatus::UpdateFailed | ServerStatus::CompileFailed
+                            );
+                            let _ = transitions.send(Transition {
+                                from,
+                                to: ServerStatus::Staging(Box::new(status)),
+                                rev: Some(staging.rev.clone()),
+                                version: staging.version.clone(),
+                                diagnostics: report.diagnostics.clone(),
+                                progress: report.progress,
+                            });
+                            // The running gameserver stays, there is nothing to switch to anymore.
+                            if failed {
+                                report.staging = None;
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+                status if status != report.status => {
+                    let from = std::mem::replace(&mut report.status, status.clone());
+                    // Nobody listening is not an error.
+                    let _ = transitions.send(Transition {
+                        from,
+                        to: status,
+                        rev: report.rev.clone(),
+                        version: report.version.clone(),
+                        diagnostics: report.diagnostics.clone(),
+                        progress: report.progress,
+                    });
+                }
+                _ => {}
+            }
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
     async fn setup(
         reporter: mpsc::UnboundedSender<ServerStatus>,
+        mut pipes: Pipes,
+        paths: Paths,
+        checkout: PathBuf,
         rev: Rev,
         args: LinkedHashSet<String>,
         cargo_args: LinkedHashSet<String>,
         envs: HashMap<String, String>,
+        policy: RestartPolicy,
     ) {
         let mut reporter = Some(reporter);
         // Update Repository.
-        Self::run_update(&mut reporter, &rev).await;
+        Self::run_update(&mut reporter, &checkout, &rev).await;
         // Query new version
-        Self::run_version(&mut reporter).await;
+        Self::run_version(&mut reporter, &checkout).await;
         // Compile server
-        Self::run_compile(&mut reporter, &cargo_args).await;
-        // Start Server
-        Self::run_server(&mut reporter, &args, &cargo_args, &envs).await;
+        let executable = Self::run_compile(&mut reporter, &paths, &checkout, &cargo_args).await;
+        // Start Server and keep it running as the policy demands.
+        if let Some(executable) = executable {
+            Self::run_server(
+                &mut reporter,
+                &mut pipes,
+                &paths.userdata(&cargo_args),
+                &checkout,
+                &executable,
+                &args,
+                &envs,
+                policy,
+            )
+            .await;
+        }
+    }
+
+    /// Like [`Self::setup`], but stops after compiling and hands the build over through `done`.
+    /// Hands over `None` if the build failed.
+    async fn setup_staging(
+        reporter: mpsc::UnboundedSender<ServerStatus>,
+        paths: Paths,
+        checkout: PathBuf,
+        rev: Rev,
+        cargo_args: LinkedHashSet<String>,
+        done: oneshot::Sender<Option<(PathBuf, PathBuf)>>,
+    ) {
+        // Everything this pipeline reports belongs to the staging.
+        let (staging, mut reports) = mpsc::unbounded_channel();
+        let forward = async move {
+            while let Some(status) = reports.recv().await {
+                let _ = reporter.send(ServerStatus::Staging(Box::new(status)));
+            }
+        };
+        let build = async move {
+            let mut reporter = Some(staging);
+            // Create worktree
+            Self::run_worktree(&mut reporter, &paths.home, &checkout).await;
+            // Update Repository.
+            Self::run_update(&mut reporter, &checkout, &rev).await;
+            // Query new version
+            Self::run_version(&mut reporter, &checkout).await;
+            // Compile server
+            let executable = Self::run_compile(&mut reporter, &paths, &checkout, &cargo_args).await;
+            match (reporter, executable) {
+                (Some(reporter), Some(executable)) => {
+                    let _ = reporter.send(ServerStatus::Ready);
+                    let _ = done.send(Some((checkout, executable)));
+                }
+                _ => {
+                    let _ = done.send(None);
+                }
+            }
+        };
+        tokio::join!(forward, build);
+    }
+
+    /// Adds `checkout` as a worktree of the repository in `home` unless it exists already.
+    async fn run_worktree(
+        report: &mut Option<mpsc::UnboundedSender<ServerStatus>>,
+        home: &Path,
+        checkout: &Path,
+    ) {
+        let reporter = match report {
+            Some(report) => report,
+            None => return,
+        };
+        if checkout.join("Cargo.toml").exists() {
+            return;
+        }
+        let _ = reporter.send(ServerStatus::Updating);
+
+        log::info!("Creating worktree {}...", checkout.display());
+
+        // Forget about worktrees which have been deleted.
+        let mut prune = Command::new("git");
+        prune.current_dir(home);
+        prune.args(["worktree", "prune"]);
+
+        let mut add = Command::new("git");
+        add.current_dir(home);
+        add.args(["worktree", "add", "--detach"]);
+        add.arg(std::path::absolute(checkout).unwrap_or_else(|_| checkout.to_path_buf()));
+
+        if let Err(e) = utils::execute("git", prune).await {
+            log::error!("Failed to prune worktrees: {}", e);
+            let _ = reporter.send(ServerStatus::UpdateFailed);
+            report.take();
+        } else if let Err(e) = utils::execute("git", add).await {
+            log::error!("Failed to create worktree: {}", e);
+            let _ = reporter.send(ServerStatus::UpdateFailed);
+            report.take();
+        }
     }
 
-    async fn run_update(report: &mut Option<mpsc::UnboundedSender<ServerStatus>>, rev: &Rev) {
+    async fn run_update(
+        report: &mut Option<mpsc::UnboundedSender<ServerStatus>>,
+        checkout: &Path,
+        rev: &Rev,
+    ) {
         let reporter = match report {
             Some(report) => report,
             None => return,
@@ -190,21 +1060,26 @@ impl Server {
         log::info!("Updating repository...");
 
         let mut fetch = Command::new("git");
-        fetch.current_dir(PathBuf::from("veloren"));
-        fetch.args(["fetch", "--all"]);
-
-        let mut checkout = Command::new("git");
-        checkout.current_dir(PathBuf::from("veloren"));
-        checkout.args(["checkout", &rev.to_string(), "-f"]);
+        fetch.current_dir(checkout);
+        match rev.remote_ref() {
+            // Fetch it to the same ref locally to check it out.
+            Some(name) => fetch.args(["fetch", "origin", &format!("+{0}:{0}", name)]),
+            None => fetch.args(["fetch", "--all"]),
+        };
 
-        let mut reset = Command::new("git");
-        reset.current_dir(PathBuf::from("veloren"));
+        // Detached, as a branch can't be checked out in two worktrees at once.
+        let mut switch = Command::new("git");
+        switch.current_dir(checkout);
+        switch.args(["checkout", "--detach", "-f"]);
         match rev {
             Rev::Branch(branch) => {
-                reset.args(["reset", "--hard", &format!("origin/{}", branch)]);
+                switch.arg(format!("origin/{}", branch));
             }
             Rev::Commit(commit) => {
-                reset.args(["reset", "--hard", commit]);
+                switch.arg(commit);
+            }
+            Rev::Tag(_) | Rev::MergeRequest(_) => {
+                switch.args(rev.remote_ref());
             }
         }
 
@@ -212,18 +1087,17 @@ impl Server {
             log::error!("Failed to fetch updates: {}", e);
             let _ = reporter.send(ServerStatus::UpdateFailed);
             report.take();
-        } else if let Err(e) = utils::execute("git", checkout).await {
+        } else if let Err(e) = utils::execute("git", switch).await {
             log::error!("Failed to checkout updates: {}", e);
             let _ = reporter.send(ServerStatus::UpdateFailed);
             report.take();
-        } else if let Err(e) = utils::execute("git", reset).await {
-            log::error!("Failed to reset to updates: {}", e);
-            let _ = reporter.send(ServerStatus::UpdateFailed);
-            report.take();
         }
     }
 
-    async fn run_version(report: &mut Option<mpsc::UnboundedSender<ServerStatus>>) {
+    async fn run_version(
+        report: &mut Option<mpsc::UnboundedSender<ServerStatus>>,
+        checkout: &Path,
+    ) {
         let reporter = match report {
             Some(report) => report,
             None => return,
@@ -231,7 +1105,7 @@ impl Server {
 
         log::info!("Querying Git commit...");
         let mut cmd = Command::new("git");
-        cmd.current_dir(PathBuf::from("veloren"));
+        cmd.current_dir(checkout);
         cmd.arg("rev-parse");
         cmd.arg("--short");
         cmd.arg("HEAD");
@@ -250,70 +1124,215 @@ impl Server {
 
     async fn run_compile(
         report: &mut Option<mpsc::UnboundedSender<ServerStatus>>,
+        paths: &Paths,
+        checkout: &Path,
         cargo_args: &LinkedHashSet<String>,
-    ) {
+    ) -> Option<PathBuf> {
         let reporter = match report {
             Some(report) => report,
-            None => return,
+            None => return None,
         };
         let _ = reporter.send(ServerStatus::Compiling);
 
-        let mut cmd = Command::new("cargo");
-        cmd.current_dir(PathBuf::from("veloren"));
-        cmd.env_remove("RUSTUP_TOOLCHAIN"); // Clean up env vars during development.
+        let mut cmd = Self::cargo(paths, checkout);
         cmd.arg("build");
-        cmd.args(["--bin", "veloren-server-cli"]);
+        cmd.args(["--bin", BINARY]);
+        cmd.arg("--message-format=json");
         cmd.args(cargo_args);
 
         log::info!("Compiling... [{:?}]", cmd);
 
-        if let Err(e) = utils::execute("cargo", cmd).await {
-            log::error!("Failed to compile: {}", e);
-            let _ = reporter.send(ServerStatus::CompileFailed);
-            report.take();
+        let (output, mut lines) = mpsc::unbounded_channel();
+        let mut build_log = BuildLog::new();
+        let (status, ()) = tokio::join!(utils::execute_captured(cmd, output), async {
+            while let Some(line) = lines.recv().await {
+                if build_log.push(line) {
+                    let _ = reporter.send(ServerStatus::Progress(build_log.progress()));
+                }
+            }
+        });
+
+        if let Err(e) = build_log.save(&paths.build_log).await {
+            log::error!("Failed to save build log: {}", e);
         }
+
+        let error = match status {
+            Ok(status) if status.success() => match build_log.executable() {
+                Some(executable) => {
+                    let deployed = paths.deployed(checkout);
+                    match Self::copy_executable(executable, &deployed).await {
+                        Ok(()) => return Some(deployed),
+                        Err(e) => e,
+                    }
+                }
+                None => anyhow::anyhow!("Build did not produce {}", BINARY),
+            },
+            Ok(status) => anyhow::anyhow!("Process exited with: {:?}", status),
+            Err(e) => e,
+        };
+        log::error!("Failed to compile: {}", error);
+        let _ = reporter.send(ServerStatus::Diagnostics(build_log.diagnostics().to_vec()));
+        let _ = reporter.send(ServerStatus::CompileFailed);
+        report.take();
+        None
     }
 
+    #[allow(clippy::too_many_arguments)]
     async fn run_server(
         report: &mut Option<mpsc::UnboundedSender<ServerStatus>>,
+        pipes: &mut Pipes,
+        userdata: &Path,
+        checkout: &Path,
+        executable: &Path,
         args: &LinkedHashSet<String>,
-        cargo_args: &LinkedHashSet<String>,
         envs: &HashMap<String, String>,
+        policy: RestartPolicy,
     ) {
         let reporter = match report {
             Some(report) => report,
             None => return,
         };
-        let _ = reporter.send(ServerStatus::Online);
 
-        let mut cmd = Command::new("cargo");
-        cmd.current_dir(PathBuf::from("veloren"));
-        cmd.env_remove("RUSTUP_TOOLCHAIN"); // Clean up env vars during development.
-        cmd.arg("run");
-        cmd.args(["--bin", "veloren-server-cli"]);
-        cmd.args(cargo_args);
-        cmd.arg("--");
-        cmd.args(args);
+        let mut retries = 0;
+        loop {
+            let _ = reporter.send(ServerStatus::Online);
 
-        cmd.envs(envs);
+            let mut cmd = Command::new(executable);
+            cmd.current_dir(checkout);
+            cmd.args(args);
 
-        log::info!("Starting Veloren Server... [{:?}]", cmd);
+            // Keep the userdata in one place, no matter which checkout the server runs from.
+            cmd.env("VELOREN_USERDATA", userdata);
+            cmd.envs(envs);
 
-        if let Err(e) = utils::execute("veloren", cmd).await {
-            log::error!("Failed to start server: {}", e);
-            let _ = reporter.send(ServerStatus::RunFailed);
-            report.take();
+            // Not the whole command, the envs might be secret.
+            log::info!(
+                "Starting Veloren Server... [{} {:?}]",
+                executable.display(),
+                args
+            );
+
+            let started = Instant::now();
+            let status =
+                match utils::execute_interactive("veloren", cmd, &mut pipes.input, &pipes.output)
+                    .await
+                {
+                    Ok(status) => status,
+                    Err(e) => {
+                        log::error!("Failed to start server: {}", e);
+                        let _ = reporter.send(ServerStatus::RunFailed);
+                        report.take();
+                        return;
+                    }
+                };
+
+            if pipes.stopping.load(Ordering::Relaxed) {
+                return;
+            }
+
+            match status.success() {
+                true => log::info!("Veloren Server exited: {}", status),
+                false => log::warn!("Veloren Server exited unexpectedly: {}", status),
+            }
+            let _ = reporter.send(ServerStatus::Exited(status.code()));
+
+            if started.elapsed() >= STABLE_UPTIME {
+                retries = 0;
+            }
+            if !policy.should_restart(status.success(), retries) {
+                let _ = reporter.send(match status.success() {
+                    true => ServerStatus::Offline,
+                    false => ServerStatus::RunFailed,
+                });
+                report.take();
+                return;
+            }
+
+            let backoff = restart_backoff(retries);
+            retries += 1;
+            log::info!(
+                "Restarting Veloren Server in {:?} (attempt {})...",
+                backoff,
+                retries
+            );
+            let _ = reporter.send(ServerStatus::Restarting);
+            tokio::time::sleep(backoff).await;
         }
     }
 
-    async fn clone_repository(repo: impl ToString) -> Result<()> {
-        log::info!("Cloning repository...");
+    /// Copies the built `executable` to `deployed`, replacing the previous one.
+    async fn copy_executable(executable: &Path, deployed: &Path) -> Result<()> {
+        if let Some(dir) = deployed.parent() {
+            tokio::fs::create_dir_all(dir)
+                .await
+                .with_context(|| format!("Failed to create {}", dir.display()))?;
+        }
+        // Unlink first, the old binary might still be executed by a stopping gameserver.
+        match tokio::fs::remove_file(deployed).await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                return Err(e).with_context(|| format!("Failed to remove {}", deployed.display()))
+            }
+            _ => {}
+        }
+        tokio::fs::copy(executable, deployed)
+            .await
+            .with_context(|| format!("Failed to copy {}", executable.display()))?;
+        Ok(())
+    }
+
+    /// Cargo invocation for `checkout`, sharing one target dir between all checkouts.
+    fn cargo(paths: &Paths, checkout: &Path) -> Command {
+        let mut cmd = Command::new("cargo");
+        cmd.current_dir(checkout);
+        cmd.env_remove("RUSTUP_TOOLCHAIN"); // Clean up env vars during development.
+        cmd.env("CARGO_TARGET_DIR", paths.target());
+        cmd
+    }
+
+    async fn clone_repository(repo: impl ToString, home: &Path) -> Result<()> {
+        log::info!("Cloning repository into {}...", home.display());
         let mut cmd = Command::new("git");
         cmd.arg("clone");
         cmd.arg(repo.to_string());
+        cmd.arg(home);
 
         utils::execute("git", cmd).await?;
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn restart_backoff_doubles_up_to_the_maximum() {
+        assert_eq!(restart_backoff(0), RESTART_BACKOFF);
+        assert_eq!(restart_backoff(1), RESTART_BACKOFF * 2);
+        assert_eq!(restart_backoff(3), RESTART_BACKOFF * 8);
+        assert_eq!(restart_backoff(10), RESTART_BACKOFF_MAX);
+        assert_eq!(restart_backoff(u32::MAX), RESTART_BACKOFF_MAX);
+    }
+
+    fn args(args: &[&str]) -> LinkedHashSet<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn profile_dirs() {
+        assert_eq!(profile_dir(&args(&[])), "debug");
+        assert_eq!(profile_dir(&args(&["--release"])), "release");
+        assert_eq!(profile_dir(&args(&["-r", "--locked"])), "release");
+        assert_eq!(profile_dir(&args(&["--profile", "test"])), "debug");
+        assert_eq!(profile_dir(&args(&["--profile=bench"])), "release");
+        assert_eq!(
+            profile_dir(&args(&["--profile", "releasedebuginfo"])),
+            "releasedebuginfo"
+        );
+        assert_eq!(
+            profile_dir(&args(&["--release", "--profile=no_overflow"])),
+            "no_overflow"
+        );
+    }
+}
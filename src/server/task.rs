@@ -1,6 +1,8 @@
 use anyhow::Result;
 use futures::future::{AbortHandle, Abortable};
-use std::{future::Future, sync::atomic::AtomicBool, sync::atomic::Ordering, sync::Arc};
+use std::{
+    future::Future, sync::atomic::AtomicBool, sync::atomic::Ordering, sync::Arc, time::Duration,
+};
 use tokio::task::JoinHandle;
 
 /// Piece of work which can be cancelled
@@ -36,6 +38,16 @@ impl Task {
         self.handle.await?
     }
 
+    /// Waits up to `timeout` for the task to finish on its own.
+    ///
+    /// Hands the task back if it is still running afterwards.
+    pub async fn wait(mut self, timeout: Duration) -> Result<(), Self> {
+        match tokio::time::timeout(timeout, &mut self.handle).await {
+            Ok(_) => Ok(()),
+            Err(_) => Err(self),
+        }
+    }
+
     /// Cancels the task and joins it.
     pub async fn cancel(self) {
         self.shutdown.abort();
@@ -0,0 +1,243 @@
+use super::build::BINARY;
+use crate::utils;
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::{Path, PathBuf},
+};
+use tokio::process::Command;
+
+/// Flags of `cargo build` which don't take a value.
+const FLAGS: [&str; 15] = [
+    "--release",
+    "-r",
+    "--all-features",
+    "--no-default-features",
+    "--locked",
+    "--frozen",
+    "--offline",
+    "--verbose",
+    "-v",
+    "-vv",
+    "--quiet",
+    "-q",
+    "--timings",
+    "--keep-going",
+    "--ignore-rust-version",
+];
+/// Options of `cargo build` which take a value.
+/// Arguments are passed one by one, so the value has to be attached with `=`.
+const OPTIONS: [&str; 3] = ["--features", "--profile", "--jobs"];
+/// Profiles cargo knows without them being declared.
+const BUILTIN_PROFILES: [&str; 4] = ["dev", "release", "test", "bench"];
+
+/// Output of `cargo metadata`, as far as it is of interest.
+#[derive(Debug, Deserialize)]
+struct Metadata {
+    packages: Vec<Package>,
+    workspace_root: PathBuf,
+}
+
+#[derive(Debug, Deserialize)]
+struct Package {
+    name: String,
+    features: BTreeMap<String, Vec<String>>,
+}
+
+/// What the workspace of a checkout allows to be passed to cargo.
+#[derive(Debug)]
+pub struct Workspace {
+    /// Features of the gameserver package.
+    features: BTreeSet<String>,
+    /// Built-in profiles and those declared in the workspace.
+    profiles: BTreeSet<String>,
+}
+
+impl Workspace {
+    /// Queries the workspace `checkout` belongs to.
+    pub async fn query(checkout: &Path) -> Result<Self> {
+        let mut cmd = Command::new("cargo");
+        cmd.current_dir(checkout);
+        cmd.env_remove("RUSTUP_TOOLCHAIN");
+        cmd.args(["metadata", "--format-version=1", "--no-deps"]);
+        let metadata: Metadata = serde_json::from_str(&utils::aquire_output(&mut cmd).await?)
+            .context("Failed to parse cargo metadata")?;
+
+        // The gameserver binary lives in a package of the same name.
+        let features = metadata
+            .packages
+            .into_iter()
+            .find(|package| package.name == BINARY)
+            .with_context(|| format!("Workspace has no {} package", BINARY))?
+            .features
+            .into_keys()
+            .collect();
+
+        // Custom profiles are only found in the manifest itself.
+        let manifest = tokio::fs::read_to_string(metadata.workspace_root.join("Cargo.toml"))
+            .await
+            .context("Failed to read workspace manifest")?;
+        let mut profiles: BTreeSet<String> = manifest
+            .lines()
+            .filter_map(|line| line.trim().strip_prefix("[profile."))
+            .filter_map(|line| line.strip_suffix(']'))
+            // Skip tables like `[profile.dev.package.foo]`.
+            .filter(|name| !name.contains('.'))
+            .map(|name| name.to_string())
+            .collect();
+        profiles.extend(BUILTIN_PROFILES.iter().map(|profile| profile.to_string()));
+
+        Ok(Self { features, profiles })
+    }
+
+    /// Checks whether cargo accepts `arg`, explaining why not and what would be valid otherwise.
+    pub fn validate(&self, arg: &str) -> Result<(), String> {
+        if FLAGS.contains(&arg) {
+            return Ok(());
+        }
+        match arg.split_once('=').map_or(arg, |(option, _)| option) {
+            // Builds for another target end up in a directory of their own.
+            "--target" => {
+                return Err(
+                    "`--target` can't be used, the gameserver wouldn't be found after the build."
+                        .to_string(),
+                )
+            }
+            "--config" => {
+                return Err(
+                    "`--config` can't be used, cargo configs can run arbitrary commands."
+                        .to_string(),
+                )
+            }
+            _ => {}
+        }
+
+        let (option, value) = match arg.split_once('=') {
+            Some(option) => option,
+            None if OPTIONS.contains(&arg) => {
+                return Err(format!("`{}` needs a value, e.g. `{}=...`.", arg, arg));
+            }
+            None => return Err(self.unknown(arg)),
+        };
+
+        match option {
+            "--features" => {
+                for feature in value.split([',', ' ']) {
+                    let feature = feature
+                        .strip_prefix(&format!("{}/", BINARY))
+                        .unwrap_or(feature);
+                    // Features of dependencies are up to them.
+                    if feature.is_empty() || feature.contains('/') {
+                        continue;
+                    }
+                    if !self.features.contains(feature) {
+                        return Err(format!(
+                            "`{}` is not a feature of {}. Valid features: {}",
+                            feature,
+                            BINARY,
+                            list(&self.features)
+                        ));
+                    }
+                }
+                Ok(())
+            }
+            "--profile" => match self.profiles.contains(value) {
+                true => Ok(()),
+                false => Err(format!(
+                    "`{}` is not a profile of the workspace. Valid profiles: {}",
+                    value,
+                    list(&self.profiles)
+                )),
+            },
+            option if OPTIONS.contains(&option) => Ok(()),
+            _ => Err(self.unknown(arg)),
+        }
+    }
+
+    /// Explains that `arg` is no known cargo argument.
+    fn unknown(&self, arg: &str) -> String {
+        let options: Vec<_> = OPTIONS
+            .iter()
+            .map(|option| format!("{}=", option))
+            .collect();
+        format!(
+            "`{}` is not a known cargo argument. Valid flags: {}, options: {}",
+            arg,
+            list(FLAGS.iter()),
+            list(&options)
+        )
+    }
+}
+
+/// Formats `items` as a list of inline code.
+fn list<T: AsRef<str>>(items: impl IntoIterator<Item = T>) -> String {
+    items
+        .into_iter()
+        .map(|item| format!("`{}`", item.as_ref()))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn workspace() -> Workspace {
+        Workspace {
+            features: ["worldgen", "plugins"]
+                .iter()
+                .map(|f| f.to_string())
+                .collect(),
+            profiles: ["dev", "release", "releasedebuginfo"]
+                .iter()
+                .map(|p| p.to_string())
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn flags() {
+        assert!(workspace().validate("--release").is_ok());
+        assert!(workspace().validate("-vv").is_ok());
+        assert!(workspace().validate("--releases").is_err());
+    }
+
+    #[test]
+    fn options_need_attached_values() {
+        assert!(workspace().validate("--jobs=4").is_ok());
+        assert!(workspace().validate("--jobs").is_err());
+        assert!(workspace().validate("--profile").is_err());
+    }
+
+    #[test]
+    fn features() {
+        let workspace = workspace();
+        assert!(workspace.validate("--features=worldgen").is_ok());
+        assert!(workspace
+            .validate("--features=veloren-server-cli/worldgen,plugins")
+            .is_ok());
+        assert!(workspace.validate("--features=common/simd").is_ok());
+        assert!(workspace.validate("--features=worldgen,tracy").is_err());
+    }
+
+    #[test]
+    fn profiles() {
+        let workspace = workspace();
+        assert!(workspace.validate("--profile=releasedebuginfo").is_ok());
+        assert!(workspace.validate("--profile=fast").is_err());
+    }
+
+    #[test]
+    fn target_and_config_are_refused() {
+        let workspace = workspace();
+        assert!(workspace.validate("--target").is_err());
+        assert!(workspace
+            .validate("--target=x86_64-unknown-linux-musl")
+            .is_err());
+        assert!(workspace.validate("--config").is_err());
+        assert!(workspace
+            .validate("--config=target.x86_64-unknown-linux-gnu.runner='sh'")
+            .is_err());
+    }
+}
@@ -1,7 +1,7 @@
 use anyhow::{Context, Result};
 use config::{Config, ConfigError, Environment, File};
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use std::{collections::BTreeMap, path::PathBuf};
 
 const FILENAME: &str = "settings.yaml";
 
@@ -26,6 +26,44 @@ pub struct Settings {
     pub web_address: String,
     /// Gameservers's address.
     pub gameserver_address: String,
+    /// Where the repository of the gameserver above is cloned to.
+    pub checkout: PathBuf,
+    /// Where the gameserver above keeps its saves and configs.
+    /// Defaults to the `userdata` dir next to the binary of the build profile in use.
+    pub userdata: Option<PathBuf>,
+    /// Name of the gameserver above, which commands use by default.
+    pub gameserver_name: String,
+    /// Further gameservers by name.
+    pub instances: BTreeMap<String, InstanceSettings>,
+    /// Seconds the gameserver gets to shut down gracefully before it is killed.
+    pub shutdown_grace_period: u64,
+    /// Discord channel to announce server status changes in.
+    pub notification_channel: Option<u64>,
+    /// Discord channel to mirror the audit log of privileged commands to.
+    pub audit_channel: Option<u64>,
+    /// Seconds between checks for new commits on the followed branch.
+    pub follow_interval: u64,
+    /// Where backups of the userdata are kept, in a dir per gameserver.
+    pub backup_dir: PathBuf,
+    /// Hours between scheduled backups. 0 disables them.
+    pub backup_interval: u64,
+    /// How many of the most recent backups are kept.
+    pub backup_keep_last: usize,
+    /// For how many days the last backup of the day is kept.
+    pub backup_keep_daily: usize,
+}
+
+/// Settings of a further gameserver.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InstanceSettings {
+    /// Gameservers's address.
+    pub address: String,
+    /// Where the repository is cloned to.
+    pub checkout: PathBuf,
+    /// Where the gameserver keeps its saves and configs. The ports are set in its
+    /// `server-cli/settings.ron`. Defaults to the `userdata` dir next to the binary.
+    #[serde(default)]
+    pub userdata: Option<PathBuf>,
 }
 
 impl Default for Settings {
@@ -39,11 +77,59 @@ impl Default for Settings {
             web_username: String::from("WEB_LOGS_USERNAME_HERE"),
             web_password: String::from("WEB_LOGS_PASSWORD_HERE"),
             gameserver_address: String::from("GAMESERVER_ADDRESS_HERE"),
+            checkout: PathBuf::from("veloren"),
+            userdata: None,
+            gameserver_name: String::from("main"),
+            instances: BTreeMap::new(),
+            shutdown_grace_period: 30,
+            notification_channel: None,
+            audit_channel: None,
+            follow_interval: 120,
+            backup_dir: PathBuf::from("backups"),
+            backup_interval: 24,
+            backup_keep_last: 10,
+            backup_keep_daily: 7,
         }
     }
 }
 
 impl Settings {
+    /// All gameservers by name, including the default one.
+    /// Fails if they'd get in each other's way.
+    pub fn gameservers(&self) -> Result<BTreeMap<String, InstanceSettings>> {
+        if self.instances.contains_key(&self.gameserver_name) {
+            anyhow::bail!(
+                "The instance {} has the name of the default gameserver (gameserver_name).",
+                self.gameserver_name
+            );
+        }
+        let mut gameservers = self.instances.clone();
+        gameservers.insert(
+            self.gameserver_name.clone(),
+            InstanceSettings {
+                address: self.gameserver_address.clone(),
+                checkout: self.checkout.clone(),
+                userdata: self.userdata.clone(),
+            },
+        );
+
+        // Builds and checkouts of different revs would clash.
+        let mut checkouts = BTreeMap::new();
+        for (name, gameserver) in &gameservers {
+            let checkout = std::path::absolute(&gameserver.checkout)
+                .unwrap_or_else(|_| gameserver.checkout.clone());
+            if let Some(other) = checkouts.insert(checkout, name) {
+                anyhow::bail!(
+                    "The gameservers {} and {} share the checkout {}.",
+                    other,
+                    name,
+                    gameserver.checkout.display()
+                );
+            }
+        }
+        Ok(gameservers)
+    }
+
     pub fn new() -> Result<Self, ConfigError> {
         let settings_path = std::env::var("BOT_SETTINGS").unwrap_or_else(|_| FILENAME.to_string());
 
@@ -1,17 +1,24 @@
 use anyhow::{Context, Result};
 use config::{Config, ConfigError, File};
 use linked_hash_set::LinkedHashSet;
-use poise::serenity_prelude::UserId;
+use poise::serenity_prelude::{RoleId, UserId};
 use serde::{Deserialize, Serialize};
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{BTreeMap, HashMap, HashSet, VecDeque},
     fmt::Display,
-    path::PathBuf,
+    path::{Path, PathBuf},
     process::Stdio,
+    time::Duration,
 };
 use tokio::process::Command;
 
 const FILENAME: &str = "state.yaml";
+/// Shown instead of the value of secret environment variables.
+pub const SECRET_MASK: &str = "••••••";
+/// How many build durations are kept to estimate the next one.
+const MAX_BUILD_DURATIONS: usize = 5;
+/// How many deployments are remembered to roll back to.
+const MAX_HISTORY: usize = 10;
 
 /// Bot state which is not intended to be edited manually.
 /// Can be adjusted at runtime and post initial setup.
@@ -20,68 +27,393 @@ const FILENAME: &str = "state.yaml";
 pub struct State {
     /// Rev to compile
     rev: Rev,
-    /// Admins which are allowed to modify the server.
+    /// Admins of earlier versions, which become users with the admin role.
+    #[serde(skip_serializing)]
     admins: HashSet<u64>,
+    /// Roles granted to Discord users and Discord roles.
+    /// Only those of the main instance count, for all instances alike.
+    roles: Vec<Grant>,
     /// Arguments passed to the gameserver.
     args: LinkedHashSet<String>,
     /// Arguments passed to cargo.
     cargo: LinkedHashSet<String>,
     /// Environment variables passed to the gameserver.
     envs: HashMap<String, String>,
+    /// Environment variables passed to the gameserver whose values are never shown.
+    secrets: HashMap<String, String>,
+    /// What to do when the gameserver exits on its own.
+    restart_policy: RestartPolicy,
+    /// Statistics of previous builds.
+    builds: BuildStats,
+    /// Build new revs while the gameserver keeps running.
+    seamless: bool,
+    /// Redeploy the branch when it gets new commits.
+    follow: bool,
+    /// Jobs to run at certain times.
+    schedule: Vec<Job>,
+    /// Most recent deployments, oldest first.
+    history: VecDeque<Deployment>,
+    /// Build profiles saved by name, next to the presets.
+    profiles: BTreeMap<String, Profile>,
+    /// Where the state is saved.
+    #[serde(skip)]
+    path: PathBuf,
 }
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Rev {
     Branch(String),
     Commit(String),
+    Tag(String),
+    /// Head of a GitLab merge request.
+    MergeRequest(u64),
+}
+
+impl Rev {
+    /// What kind of rev this is, e.g. to label it.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::Branch(_) => "Branch",
+            Self::Commit(_) => "Commit",
+            Self::Tag(_) => "Tag",
+            Self::MergeRequest(_) => "Merge request",
+        }
+    }
+
+    /// The ref on the remote which isn't fetched by default.
+    pub fn remote_ref(&self) -> Option<String> {
+        match self {
+            Self::Branch(_) | Self::Commit(_) => None,
+            Self::Tag(tag) => Some(format!("refs/tags/{}", tag)),
+            Self::MergeRequest(id) => Some(format!("refs/merge-requests/{}/head", id)),
+        }
+    }
+
+    /// Parses `!1234` or `refs/merge-requests/1234/head` as merge request id.
+    fn parse_merge_request(rev: &str) -> Option<u64> {
+        rev.strip_prefix('!')
+            .or_else(|| {
+                rev.strip_prefix("refs/merge-requests/")?
+                    .strip_suffix("/head")
+            })?
+            .parse()
+            .ok()
+    }
 }
 
 impl Display for Rev {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "{}",
-            match self {
-                Self::Branch(branch) => branch,
-                Self::Commit(commit) => commit,
+        match self {
+            Self::Branch(branch) => write!(f, "{}", branch),
+            Self::Commit(commit) => write!(f, "{}", commit),
+            Self::Tag(tag) => write!(f, "{}", tag),
+            Self::MergeRequest(id) => write!(f, "!{}", id),
+        }
+    }
+}
+
+/// A rev which has been deployed to the gameserver.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Deployment {
+    pub rev: Rev,
+    /// Commit the rev resolved to, once it has been checked out.
+    pub commit: Option<String>,
+    /// Discord user who deployed it.
+    pub deployed_by: Option<u64>,
+    /// Unix timestamp of the deployment.
+    pub deployed_at: i64,
+    /// Whether the gameserver came up with it and stayed up for a while.
+    pub online: bool,
+}
+
+/// Bundle of arguments and environment variables to build and run the gameserver with.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Profile {
+    /// Arguments passed to cargo.
+    pub cargo: LinkedHashSet<String>,
+    /// Environment variables passed to the gameserver.
+    pub envs: HashMap<String, String>,
+    /// Arguments passed to the gameserver.
+    pub args: LinkedHashSet<String>,
+}
+
+impl Profile {
+    /// Cargo arguments of the profiles which are always available.
+    /// Presets keep the envs and gameserver arguments in use.
+    pub fn presets() -> BTreeMap<String, LinkedHashSet<String>> {
+        let preset = |cargo: &[&str]| cargo.iter().map(|arg| arg.to_string()).collect();
+
+        let mut presets = BTreeMap::new();
+        presets.insert("debug".to_string(), preset(&[]));
+        presets.insert("release".to_string(), preset(&["--release"]));
+        presets.insert(
+            "release-debuginfo".to_string(),
+            preset(&["--profile=releasedebuginfo"]),
+        );
+        presets.insert(
+            "tracy".to_string(),
+            preset(&["--profile=releasedebuginfo", "--features=tracy"]),
+        );
+        presets
+    }
+}
+
+/// Environment variables passed to the gameserver unless changed.
+fn default_envs() -> HashMap<String, String> {
+    let mut envs = HashMap::new();
+    envs.insert("RUST_BACKTRACE".to_string(), "1".to_string());
+    envs.insert(
+        "RUST_LOG".to_string(),
+        "debug,uvth=error,rustls=error,tiny_http=warn,veloren_network=warn,dot_vox=warn"
+            .to_string(),
+    );
+    envs
+}
+
+/// What someone is allowed to do with the bot. Each role includes the ones before it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Role {
+    /// See the configuration and logs.
+    Viewer,
+    /// Start, stop and restart the server.
+    Operator,
+    /// Change what is deployed and how it is built and run.
+    Deployer,
+    /// Manage files, roles and send commands to the gameserver.
+    Admin,
+}
+
+impl Display for Role {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Viewer => write!(f, "viewer"),
+            Self::Operator => write!(f, "operator"),
+            Self::Deployer => write!(f, "deployer"),
+            Self::Admin => write!(f, "admin"),
+        }
+    }
+}
+
+/// Who a role is granted to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Grantee {
+    /// A Discord user.
+    User(u64),
+    /// Everyone with a Discord role.
+    Role(u64),
+}
+
+/// A role granted to someone.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Grant {
+    pub grantee: Grantee,
+    pub role: Role,
+}
+
+/// A scheduled action.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Job {
+    pub id: u32,
+    pub action: Action,
+    pub repeat: Repeat,
+    /// Unix timestamp of the next run.
+    pub next: i64,
+}
+
+/// What a scheduled job does.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Action {
+    Restart,
+    /// Clean the build and restart.
+    Prune,
+    /// Switch to the rev.
+    Rev(String),
+}
+
+impl Display for Action {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Restart => write!(f, "restart"),
+            Self::Prune => write!(f, "prune"),
+            Self::Rev(rev) => write!(f, "switch to {}", rev),
+        }
+    }
+}
+
+/// How often a scheduled job runs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Repeat {
+    Once,
+    Daily,
+    Weekly,
+}
+
+impl Repeat {
+    /// Seconds between runs, if the job runs more than once.
+    pub fn interval(&self) -> Option<i64> {
+        match self {
+            Self::Once => None,
+            Self::Daily => Some(24 * 60 * 60),
+            Self::Weekly => Some(7 * 24 * 60 * 60),
+        }
+    }
+}
+
+impl Display for Repeat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Once => write!(f, "once"),
+            Self::Daily => write!(f, "daily"),
+            Self::Weekly => write!(f, "weekly"),
+        }
+    }
+}
+
+/// Statistics of previous successful builds to estimate the progress of the next one.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BuildStats {
+    /// Crates the last build consisted of.
+    pub units: Option<usize>,
+    /// Durations of the most recent builds in seconds.
+    pub durations: VecDeque<u64>,
+}
+
+impl BuildStats {
+    /// Average duration of the most recent builds.
+    pub fn average_duration(&self) -> Option<Duration> {
+        if self.durations.is_empty() {
+            return None;
+        }
+        let total: u64 = self.durations.iter().sum();
+        Some(Duration::from_secs(total / self.durations.len() as u64))
+    }
+}
+
+/// What to do when the gameserver exits without being stopped.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RestartPolicy {
+    /// Leave it offline.
+    Never,
+    /// Restart it if it crashed, giving up after `max_retries` crashes in a row.
+    OnFailure { max_retries: u32 },
+    /// Restart it no matter how it exited.
+    Always,
+}
+
+impl RestartPolicy {
+    /// Whether the gameserver should be started again after it already has been restarted `retries` times.
+    pub fn should_restart(&self, success: bool, retries: u32) -> bool {
+        match self {
+            Self::Never => false,
+            Self::OnFailure { max_retries } => !success && retries < *max_retries,
+            Self::Always => true,
+        }
+    }
+}
+
+impl Display for RestartPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Never => write!(f, "never"),
+            Self::OnFailure { max_retries } => {
+                write!(f, "on failure (up to {} times)", max_retries)
             }
-        )
+            Self::Always => write!(f, "always"),
+        }
     }
 }
 
 impl Default for State {
     fn default() -> Self {
-        let mut envs = HashMap::new();
-        envs.insert("RUST_BACKTRACE".to_string(), "1".to_string());
-        envs.insert(
-            "RUST_LOG".to_string(),
-            "debug,uvth=error,rustls=error,tiny_http=warn,veloren_network=warn,dot_vox=warn"
-                .to_string(),
-        );
-
         Self {
             rev: Rev::Branch("master".into()),
             admins: HashSet::new(),
+            roles: Vec::new(),
             args: LinkedHashSet::new(),
             cargo: LinkedHashSet::new(),
-            envs,
+            envs: default_envs(),
+            secrets: HashMap::new(),
+            restart_policy: RestartPolicy::OnFailure { max_retries: 3 },
+            builds: BuildStats::default(),
+            seamless: false,
+            follow: false,
+            schedule: Vec::new(),
+            history: VecDeque::new(),
+            profiles: BTreeMap::new(),
+            path: PathBuf::from(FILENAME),
         }
     }
 }
 
 impl State {
-    pub fn new() -> Result<Self, ConfigError> {
-        let state_path = std::env::var("BOT_STATE").unwrap_or_else(|_| FILENAME.to_string());
+    /// Loads the state of `instance`, or the default instance if `None`.
+    /// Creates and saves a default state if there is none yet.
+    pub async fn open(instance: Option<&str>) -> Result<Self> {
+        let path = Self::path(instance);
+        let mut state = match Self::new(&path) {
+            Ok(state) => state,
+            Err(_) => {
+                let state = Self {
+                    path: path.clone(),
+                    ..Default::default()
+                };
+                state.save().await?;
+                state
+            }
+        };
+        state.path = path;
+
+        if !state.admins.is_empty() {
+            for id in std::mem::take(&mut state.admins) {
+                state.roles.push(Grant {
+                    grantee: Grantee::User(id),
+                    role: Role::Admin,
+                });
+            }
+            state.save().await?;
+        }
 
-        let s = Config::builder()
-            .add_source(File::with_name(&state_path))
-            .build()?;
+        Ok(state)
+    }
+
+    pub fn new(path: &Path) -> Result<Self, ConfigError> {
+        let s = Config::builder().add_source(File::from(path)).build()?;
 
         // Deserialize entire configuration
         s.try_deserialize()
     }
 
-    pub fn admins(&self) -> HashSet<UserId> {
-        self.admins.iter().map(|s| UserId::new(*s)).collect()
+    /// Where the state of `instance` is saved. Other than the default instance get their name appended.
+    fn path(instance: Option<&str>) -> PathBuf {
+        let path =
+            PathBuf::from(std::env::var("BOT_STATE").unwrap_or_else(|_| FILENAME.to_string()));
+        match instance {
+            Some(instance) => {
+                let mut name = path.file_stem().unwrap_or_default().to_os_string();
+                name.push(format!("-{}.yaml", instance));
+                path.with_file_name(name)
+            }
+            None => path,
+        }
+    }
+
+    /// Highest role of `user`, who has the Discord roles `roles`.
+    /// Roles are global, so only ask the state of the main instance.
+    pub fn role(&self, user: UserId, roles: &[RoleId]) -> Option<Role> {
+        self.roles
+            .iter()
+            .filter(|grant| match grant.grantee {
+                Grantee::User(id) => id == user.get(),
+                Grantee::Role(id) => roles.contains(&RoleId::new(id)),
+            })
+            .map(|grant| grant.role)
+            .max()
+    }
+
+    /// Roles granted to Discord users and Discord roles.
+    pub fn grants(&self) -> &[Grant] {
+        &self.roles
     }
 
     /// Returns the git head
@@ -99,65 +431,273 @@ impl State {
         &self.cargo
     }
 
-    /// Gameserver Environment Variables
-    pub fn envs(&self) -> &HashMap<String, String> {
-        &self.envs
+    /// Gameserver Environment Variables, including the secret ones.
+    pub fn envs(&self) -> HashMap<String, String> {
+        let mut envs = self.envs.clone();
+        envs.extend(self.secrets.clone());
+        envs
+    }
+
+    /// Gameserver Environment Variables with the values of secret ones masked, fit to be shown.
+    pub fn masked_envs(&self) -> HashMap<String, String> {
+        let mut envs = self.envs.clone();
+        envs.extend(
+            self.secrets
+                .keys()
+                .map(|name| (name.clone(), SECRET_MASK.to_string())),
+        );
+        envs
+    }
+
+    /// Whether the value of the environment variable `name` must not be shown.
+    pub fn is_secret(&self, name: &str) -> bool {
+        self.secrets.contains_key(name)
+    }
+
+    /// What to do when the gameserver exits on its own.
+    pub fn restart_policy(&self) -> RestartPolicy {
+        self.restart_policy
+    }
+
+    pub async fn set_restart_policy(&mut self, policy: RestartPolicy) -> Result<()> {
+        self.restart_policy = policy;
+        self.save().await?;
+        Ok(())
+    }
+
+    /// Whether new revs are built while the gameserver keeps running.
+    pub fn seamless(&self) -> bool {
+        self.seamless
+    }
+
+    pub async fn set_seamless(&mut self, seamless: bool) -> Result<()> {
+        self.seamless = seamless;
+        self.save().await?;
+        Ok(())
+    }
+
+    /// Whether the branch is redeployed when it gets new commits.
+    pub fn follow(&self) -> bool {
+        self.follow
+    }
+
+    pub async fn set_follow(&mut self, follow: bool) -> Result<()> {
+        self.follow = follow;
+        self.save().await?;
+        Ok(())
+    }
+
+    /// Scheduled jobs.
+    pub fn schedule(&self) -> &[Job] {
+        &self.schedule
+    }
+
+    /// Schedules `action` at the unix timestamp `next` and returns the id of the job.
+    pub async fn add_job(&mut self, action: Action, repeat: Repeat, next: i64) -> Result<u32> {
+        let id = self
+            .schedule
+            .iter()
+            .map(|job| job.id + 1)
+            .max()
+            .unwrap_or(1);
+        self.schedule.push(Job {
+            id,
+            action,
+            repeat,
+            next,
+        });
+        self.save().await?;
+        Ok(id)
+    }
+
+    /// Returns whether a job with `id` existed.
+    pub async fn remove_job(&mut self, id: u32) -> Result<bool> {
+        let len = self.schedule.len();
+        self.schedule.retain(|job| job.id != id);
+        if len == self.schedule.len() {
+            return Ok(false);
+        }
+        self.save().await?;
+        Ok(true)
+    }
+
+    /// Moves `job` to its next run after `now` or removes it if it only runs once.
+    /// Returns `false` if it has been removed or changed meanwhile, then it shouldn't run.
+    pub async fn complete_job(&mut self, job: &Job, now: i64) -> Result<bool> {
+        if !self.schedule.contains(job) {
+            return Ok(false);
+        }
+        self.schedule.retain_mut(|scheduled| {
+            if scheduled != job {
+                return true;
+            }
+            match scheduled.repeat.interval() {
+                Some(interval) => {
+                    while scheduled.next <= now {
+                        scheduled.next += interval;
+                    }
+                    true
+                }
+                None => false,
+            }
+        });
+        self.save().await?;
+        Ok(true)
+    }
+
+    /// Statistics of previous builds.
+    pub fn builds(&self) -> &BuildStats {
+        &self.builds
+    }
+
+    /// Remembers a successful build of `units` crates.
+    pub async fn record_build(&mut self, units: usize, duration: Duration) -> Result<()> {
+        self.builds.units = Some(units);
+        self.builds.durations.push_back(duration.as_secs());
+        while self.builds.durations.len() > MAX_BUILD_DURATIONS {
+            self.builds.durations.pop_front();
+        }
+        self.save().await?;
+        Ok(())
+    }
+
+    /// Deployed revs, oldest first.
+    pub fn history(&self) -> &VecDeque<Deployment> {
+        &self.history
+    }
+
+    /// The `n`th most recent deployment before the current one, which stayed online.
+    pub fn known_good(&self, n: usize) -> Option<&Deployment> {
+        self.history
+            .iter()
+            .rev()
+            .skip(1)
+            .filter(|deployment| deployment.online && deployment.commit.is_some())
+            .nth(n.checked_sub(1)?)
+    }
+
+    /// Switches to `rev` and remembers who did so.
+    pub async fn deploy(&mut self, rev: Rev, by: Option<UserId>) -> Result<()> {
+        self.rev = rev.clone();
+        self.history.push_back(Deployment {
+            rev,
+            commit: None,
+            deployed_by: by.map(|id| id.get()),
+            deployed_at: chrono::Utc::now().timestamp(),
+            online: false,
+        });
+        while self.history.len() > MAX_HISTORY {
+            self.history.pop_front();
+        }
+        self.save().await?;
+        Ok(())
+    }
+
+    /// Updates the latest deployment with what the server reported about `rev`.
+    pub async fn update_deployment(
+        &mut self,
+        rev: &Rev,
+        commit: Option<String>,
+        online: bool,
+    ) -> Result<()> {
+        let deployment = match self.history.back_mut() {
+            Some(deployment) if deployment.rev == *rev => deployment,
+            _ => return Ok(()),
+        };
+        let mut changed = false;
+        if deployment.commit.is_none() && commit.is_some() {
+            deployment.commit = commit;
+            changed = true;
+        }
+        if online && !deployment.online {
+            deployment.online = true;
+            changed = true;
+        }
+        if changed {
+            self.save().await?;
+        }
+        Ok(())
     }
 
-    pub async fn set_rev<T: ToString, Y: ToString>(&mut self, rev: T, repo: Y) -> Result<bool> {
-        let mut branch_cmd = Command::new("git");
-        branch_cmd.current_dir(PathBuf::from("veloren"));
-        branch_cmd.args([
-            "ls-remote",
-            "--exit-code",
-            "--heads",
-            &repo.to_string(),
-            &rev.to_string(),
-        ]);
+    /// Switches to `rev` if it exists in `repo`, which is cloned to `checkout`.
+    pub async fn set_rev<T: ToString, Y: ToString>(
+        &mut self,
+        rev: T,
+        repo: Y,
+        checkout: &Path,
+        by: Option<UserId>,
+    ) -> Result<bool> {
+        match Self::resolve_rev(&rev.to_string(), &repo.to_string(), checkout).await? {
+            Some(rev) => {
+                self.deploy(rev, by).await?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// What `rev` is in `repo`, which is cloned to `checkout`. `None` if it doesn't exist.
+    pub async fn resolve_rev(rev: &str, repo: &str, checkout: &Path) -> Result<Option<Rev>> {
+        let rev = rev.to_string();
 
-        let branch_exists = branch_cmd
+        if Self::remote_ref_exists(checkout, repo, "--heads", &rev).await? {
+            return Ok(Some(Rev::Branch(rev)));
+        }
+        if Self::remote_ref_exists(checkout, repo, "--tags", &rev).await? {
+            return Ok(Some(Rev::Tag(rev)));
+        }
+        if let Some(id) = Rev::parse_merge_request(&rev) {
+            let merge_request = Rev::MergeRequest(id);
+            let exists = match merge_request.remote_ref() {
+                Some(name) => Self::remote_ref_exists(checkout, repo, "--refs", &name).await?,
+                None => false,
+            };
+            return Ok(exists.then_some(merge_request));
+        }
+
+        let mut fetch_cmd = Command::new("git");
+        fetch_cmd.current_dir(checkout);
+        fetch_cmd.args(["fetch", "--all"]);
+
+        fetch_cmd
             .stdout(Stdio::null())
             .stderr(Stdio::null())
             .status()
-            .await?
+            .await
+            .context("Failed to fetch repository updates")?;
+
+        let mut commit_cmd = Command::new("git");
+        commit_cmd.current_dir(checkout);
+        commit_cmd.args(["cat-file", "-e", &rev]);
+
+        let commit_exists = commit_cmd
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .await
+            .context("Failed to check if commit exists")?
             .success();
 
-        if branch_exists {
-            self.rev = Rev::Branch(rev.to_string());
-            self.save().await?;
-            return Ok(true);
-        } else {
-            let mut fetch_cmd = Command::new("git");
-            fetch_cmd.current_dir(PathBuf::from("veloren"));
-            fetch_cmd.args(["fetch", "--all"]);
-
-            fetch_cmd
-                .stdout(Stdio::null())
-                .stderr(Stdio::null())
-                .status()
-                .await
-                .context("Failed to fetch repository updates")?;
-
-            let mut commit_cmd = Command::new("git");
-            commit_cmd.current_dir(PathBuf::from("veloren"));
-            commit_cmd.args(["cat-file", "-e", &rev.to_string()]);
-
-            let commit_exists = commit_cmd
-                .stdout(Stdio::null())
-                .stderr(Stdio::null())
-                .status()
-                .await
-                .context("Failed to check if commit exists")?
-                .success();
-
-            if commit_exists {
-                self.rev = Rev::Commit(rev.to_string());
-                self.save().await?;
-                return Ok(true);
-            }
-        }
+        Ok(commit_exists.then_some(Rev::Commit(rev)))
+    }
 
-        Ok(false)
+    /// Whether `repo` has a ref of `kind` (e.g. `--heads`) matching `pattern`.
+    async fn remote_ref_exists(
+        checkout: &Path,
+        repo: &str,
+        kind: &str,
+        pattern: &str,
+    ) -> Result<bool> {
+        let mut cmd = Command::new("git");
+        cmd.current_dir(checkout);
+        cmd.args(["ls-remote", "--exit-code", kind, repo, pattern]);
+
+        Ok(cmd
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .await?
+            .success())
     }
 
     pub async fn add_arg(&mut self, arg: &str) -> Result<()> {
@@ -209,54 +749,114 @@ impl State {
     }
 
     pub async fn add_env(&mut self, name: &str, value: &str) -> Result<()> {
+        self.secrets.remove(name);
         self.envs.insert(name.to_string(), value.to_string());
         self.save().await?;
         Ok(())
     }
 
+    /// Adds an environment variable whose value is never shown.
+    pub async fn add_secret_env(&mut self, name: &str, value: &str) -> Result<()> {
+        self.envs.remove(name);
+        self.secrets.insert(name.to_string(), value.to_string());
+        self.save().await?;
+        Ok(())
+    }
+
     pub async fn remove_env(&mut self, name: &str) -> Result<()> {
         self.envs.remove(name);
+        self.secrets.remove(name);
         self.save().await?;
         Ok(())
     }
 
     pub async fn reset_envs(&mut self) -> Result<()> {
-        self.envs.clear();
-        // Add back default envs.
-        self.envs
-            .insert("RUST_BACKTRACE".to_string(), "1".to_string());
-        self.envs.insert(
-            "RUST_LOG".to_string(),
-            "debug,uvth=error,rustls=error,tiny_http=warn,veloren_network=warn,dot_vox=warn"
-                .to_string(),
-        );
+        self.envs = default_envs();
+        self.secrets.clear();
         self.save().await?;
         Ok(())
     }
 
-    /// adds an admin and saves it to the settings
-    pub async fn add_admin(&mut self, id: u64) -> Result<()> {
-        self.admins.insert(id);
+    /// Presets and saved profiles by name, as they would be used.
+    /// Saved profiles can't shadow presets.
+    pub fn profiles(&self) -> BTreeMap<String, Profile> {
+        let mut profiles = self.profiles.clone();
+        profiles.extend(Profile::presets().into_iter().map(|(name, cargo)| {
+            let profile = Profile {
+                cargo,
+                ..self.current_profile()
+            };
+            (name, profile)
+        }));
+        profiles
+    }
+
+    /// The cargo arguments, envs and gameserver arguments currently in use.
+    pub fn current_profile(&self) -> Profile {
+        Profile {
+            cargo: self.cargo.clone(),
+            envs: self.envs.clone(),
+            args: self.args.clone(),
+        }
+    }
 
+    /// Saves the current cargo arguments, envs and gameserver arguments as `name`.
+    pub async fn save_profile(&mut self, name: &str) -> Result<()> {
+        self.profiles
+            .insert(name.to_string(), self.current_profile());
         self.save().await?;
         Ok(())
     }
 
-    /// removes an admin and saves it to the settings
-    pub async fn remove_admin(&mut self, id: u64) -> Result<()> {
-        self.admins.remove(&id);
+    /// Replaces the cargo arguments, envs and gameserver arguments with the ones of `profile`.
+    pub async fn use_profile(&mut self, profile: Profile) -> Result<()> {
+        self.cargo = profile.cargo;
+        self.envs = profile.envs;
+        self.args = profile.args;
+        self.save().await?;
+        Ok(())
+    }
 
+    /// Grants `role` to `grantee`, replacing the role it had.
+    pub async fn grant(&mut self, grantee: Grantee, role: Role) -> Result<()> {
+        self.roles.retain(|grant| grant.grantee != grantee);
+        self.roles.push(Grant { grantee, role });
         self.save().await?;
         Ok(())
     }
 
+    /// Takes the role of `grantee` away. Returns whether it had one.
+    pub async fn revoke(&mut self, grantee: Grantee) -> Result<bool> {
+        let len = self.roles.len();
+        self.roles.retain(|grant| grant.grantee != grantee);
+        if self.roles.len() == len {
+            return Ok(false);
+        }
+        self.save().await?;
+        Ok(true)
+    }
+
+    /// The state as it is saved, with the values of secret envs masked.
+    pub fn masked(&self) -> Result<serde_yaml::Value> {
+        let mut state = serde_yaml::to_value(self).context("Failed to serialize state")?;
+        if let Some(secrets) = state
+            .get_mut("secrets")
+            .and_then(|secrets| secrets.as_mapping_mut())
+        {
+            for (_, value) in secrets.iter_mut() {
+                *value = SECRET_MASK.into();
+            }
+        }
+        Ok(state)
+    }
+
     pub async fn save(&self) -> Result<()> {
         use tokio::io::AsyncWriteExt;
 
-        let state_path = std::env::var("BOT_STATE").unwrap_or_else(|_| FILENAME.to_string());
-
-        let _ = tokio::fs::create_dir_all(PathBuf::from(&state_path).parent().unwrap()).await;
-        let mut file = tokio::fs::File::create(&state_path).await?;
+        if let Some(parent) = self.path.parent() {
+            let _ = tokio::fs::create_dir_all(parent).await;
+        }
+        let mut file = tokio::fs::File::create(&self.path).await?;
         file.write_all(
             serde_yaml::to_string(&self)
                 .context("Failed to serialize state")?
@@ -267,3 +867,136 @@ impl State {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn never_restarts() {
+        let policy = RestartPolicy::Never;
+        assert!(!policy.should_restart(false, 0));
+        assert!(!policy.should_restart(true, 0));
+    }
+
+    #[test]
+    fn restarts_on_failure_up_to_max_retries() {
+        let policy = RestartPolicy::OnFailure { max_retries: 2 };
+        assert!(policy.should_restart(false, 0));
+        assert!(policy.should_restart(false, 1));
+        assert!(!policy.should_restart(false, 2));
+        assert!(!policy.should_restart(true, 0));
+    }
+
+    #[test]
+    fn always_restarts() {
+        let policy = RestartPolicy::Always;
+        assert!(policy.should_restart(true, 0));
+        assert!(policy.should_restart(false, 100));
+    }
+
+    fn deployment(commit: Option<&str>, online: bool) -> Deployment {
+        Deployment {
+            rev: Rev::Branch("master".into()),
+            commit: commit.map(str::to_string),
+            deployed_by: None,
+            deployed_at: 0,
+            online,
+        }
+    }
+
+    #[test]
+    fn known_good_skips_current_and_failed_deployments() {
+        let state = State {
+            history: VecDeque::from([
+                deployment(Some("a"), true),
+                deployment(Some("b"), false),
+                deployment(None, true),
+                deployment(Some("c"), true),
+                deployment(Some("d"), true),
+            ]),
+            ..State::default()
+        };
+        let commit = |n| state.known_good(n).and_then(|d| d.commit.as_deref());
+        assert_eq!(commit(0), None);
+        assert_eq!(commit(1), Some("c"));
+        assert_eq!(commit(2), Some("a"));
+        assert_eq!(commit(3), None);
+    }
+
+    #[test]
+    fn parses_merge_requests() {
+        assert_eq!(Rev::parse_merge_request("!1234"), Some(1234));
+        assert_eq!(
+            Rev::parse_merge_request("refs/merge-requests/1234/head"),
+            Some(1234)
+        );
+        assert_eq!(Rev::parse_merge_request("1234"), None);
+        assert_eq!(Rev::parse_merge_request("!master"), None);
+        assert_eq!(Rev::parse_merge_request("refs/merge-requests/1234"), None);
+    }
+
+    #[test]
+    fn remote_refs() {
+        assert_eq!(Rev::Branch("master".into()).remote_ref(), None);
+        assert_eq!(Rev::Commit("abc123".into()).remote_ref(), None);
+        assert_eq!(
+            Rev::Tag("v0.16.0".into()).remote_ref().as_deref(),
+            Some("refs/tags/v0.16.0")
+        );
+        assert_eq!(
+            Rev::MergeRequest(1234).remote_ref().as_deref(),
+            Some("refs/merge-requests/1234/head")
+        );
+    }
+
+    const DAY: i64 = 24 * 60 * 60;
+
+    /// A state with `schedule`, saved to a file of its own in the temp dir.
+    fn scheduled(name: &str, schedule: Vec<Job>) -> State {
+        let path =
+            std::env::temp_dir().join(format!("veloren-bot-{}-{}.yaml", name, std::process::id()));
+        State {
+            schedule,
+            path,
+            ..State::default()
+        }
+    }
+
+    fn job(id: u32, repeat: Repeat, next: i64) -> Job {
+        Job {
+            id,
+            action: Action::Restart,
+            repeat,
+            next,
+        }
+    }
+
+    #[tokio::test]
+    async fn completed_jobs_run_again_or_are_removed() {
+        let once = job(1, Repeat::Once, 100);
+        let daily = job(2, Repeat::Daily, 100);
+        let mut state = scheduled("complete", vec![once.clone(), daily.clone()]);
+
+        assert!(state.complete_job(&once, 100).await.unwrap());
+        assert_eq!(state.schedule, [job(2, Repeat::Daily, 100)]);
+        // Runs missed while the bot was offline are skipped.
+        assert!(state.complete_job(&daily, 100 + 2 * DAY).await.unwrap());
+        assert_eq!(state.schedule, [job(2, Repeat::Daily, 100 + 3 * DAY)]);
+        let _ = std::fs::remove_file(&state.path);
+    }
+
+    #[tokio::test]
+    async fn changed_jobs_are_not_completed() {
+        let daily = job(1, Repeat::Daily, 100);
+        let mut state = scheduled("changed", vec![job(1, Repeat::Weekly, 100)]);
+
+        assert!(!state.complete_job(&daily, 100).await.unwrap());
+        assert!(!state
+            .complete_job(&job(2, Repeat::Once, 100), 100)
+            .await
+            .unwrap());
+        assert_eq!(state.schedule, [job(1, Repeat::Weekly, 100)]);
+        let _ = std::fs::remove_file(&state.path);
+    }
+}
@@ -0,0 +1,92 @@
+use crate::{
+    server::{ServerStatus, Transition},
+    state::{Rev, State},
+};
+use std::{sync::Arc, time::Duration};
+use tokio::{
+    sync::{
+        broadcast::{self, error::RecvError},
+        Mutex,
+    },
+    time::Instant,
+};
+
+/// How long the gameserver has to stay up before its rev counts as known-good.
+const KNOWN_GOOD_UPTIME: Duration = Duration::from_secs(2 * 60);
+
+/// Records what the server reports in the bot state until the server is gone.
+pub async fn run(state: Arc<Mutex<State>>, mut transitions: broadcast::Receiver<Transition>) {
+    // Rev the gameserver came up with and when it counts as known-good.
+    let mut starting: Option<(Rev, Instant)> = None;
+    loop {
+        let received = match &starting {
+            Some((rev, deadline)) => tokio::select! {
+                received = transitions.recv() => received,
+                _ = tokio::time::sleep_until(*deadline) => {
+                    if let Err(e) = state.lock().await.update_deployment(rev, None, true).await {
+                        log::error!("Failed to update deployment: {}", e);
+                    }
+                    starting = None;
+                    continue;
+                }
+            },
+            None => transitions.recv().await,
+        };
+        let transition = match received {
+            Ok(transition) => transition,
+            Err(RecvError::Lagged(skipped)) => {
+                log::warn!("Skipped {} server status changes.", skipped);
+                continue;
+            }
+            Err(RecvError::Closed) => return,
+        };
+
+        // Resolve the latest deployment. The version is only up to date once compiling.
+        let commit = match transition.to.unstaged() {
+            ServerStatus::Compiling => transition.version.clone(),
+            _ => None,
+        };
+        if let (Some(rev), Some(_)) = (&transition.rev, &commit) {
+            if let Err(e) = state
+                .lock()
+                .await
+                .update_deployment(rev, commit, false)
+                .await
+            {
+                log::error!("Failed to update deployment: {}", e);
+            }
+        }
+
+        // Online is reported right before the process is spawned, so only a rev which stays up
+        // for a while is worth rolling back to. Builds next to it don't touch the running one.
+        if transition.to == ServerStatus::Online {
+            starting = transition
+                .rev
+                .clone()
+                .map(|rev| (rev, Instant::now() + KNOWN_GOOD_UPTIME));
+        } else if transition.to == *transition.to.unstaged() {
+            starting = None;
+        }
+
+        // A successful build, either of the server or next to it.
+        let built = match (&transition.from, &transition.to) {
+            (ServerStatus::Compiling, ServerStatus::Online) => true,
+            (ServerStatus::Staging(from), ServerStatus::Staging(to)) => {
+                **from == ServerStatus::Compiling && **to == ServerStatus::Ready
+            }
+            _ => false,
+        };
+        if built {
+            if let Some(progress) = transition.progress {
+                if let Err(e) = state
+                    .lock()
+                    .await
+                    .record_build(progress.units, progress.elapsed)
+                    .await
+                {
+                    log::error!("Failed to record build: {}", e);
+                }
+            }
+        }
+    }
+}
@@ -1,8 +1,9 @@
 use anyhow::{Context, Result};
-use std::process::Stdio;
+use std::process::{ExitStatus, Stdio};
 use tokio::{
-    io::BufReader,
+    io::{AsyncWriteExt, BufReader},
     process::{ChildStderr, ChildStdout, Command},
+    sync::{broadcast, mpsc},
 };
 use tokio_stream::wrappers::LinesStream;
 
@@ -32,7 +33,7 @@ pub async fn execute(name: &str, mut cmd: Command) -> Result<()> {
     let stdout = child.stdout.take().unwrap(); // Safe because we setup stdout & stderr beforehand
     let stderr = child.stderr.take().unwrap();
 
-    tokio::task::spawn(print_progress(name.to_string(), stdout, stderr));
+    tokio::task::spawn(print_progress(name.to_string(), stdout, stderr, None));
     let status = child.wait().await.context("Failed to wait for process.")?;
 
     if !status.success() {
@@ -42,31 +43,126 @@ pub async fn execute(name: &str, mut cmd: Command) -> Result<()> {
     Ok(())
 }
 
+/// Execute Command, write every line received from `input` to its stdin
+/// and log stdout/stderr while forwarding it to `output`.
+///
+/// Returns how the process exited, no matter whether it succeeded.
+pub async fn execute_interactive(
+    name: &str,
+    mut cmd: Command,
+    input: &mut mpsc::UnboundedReceiver<String>,
+    output: &broadcast::Sender<String>,
+) -> Result<ExitStatus> {
+    // Not the whole command, its envs might be secret.
+    log::debug!("Executing: {:?}", cmd.as_std().get_program());
+
+    cmd.stdin(Stdio::piped());
+    cmd.stdout(Stdio::piped());
+    cmd.stderr(Stdio::piped());
+    let mut child = cmd
+        .kill_on_drop(true)
+        .spawn()
+        .context("Failed to spawn process.")?;
+
+    let mut stdin = child.stdin.take().unwrap(); // Safe because we setup stdin, stdout & stderr beforehand
+    let stdout = child.stdout.take().unwrap();
+    let stderr = child.stderr.take().unwrap();
+
+    tokio::task::spawn(print_progress(
+        name.to_string(),
+        stdout,
+        stderr,
+        Some(output.clone()),
+    ));
+
+    loop {
+        tokio::select! {
+            status = child.wait() => return status.context("Failed to wait for process."),
+            Some(line) = input.recv() => {
+                log::info!("[{}] > {}", name, line);
+                if let Err(e) = stdin.write_all(format!("{}\n", line).as_bytes()).await {
+                    log::error!("Failed to write to process stdin: {}", e);
+                } else if let Err(e) = stdin.flush().await {
+                    log::error!("Failed to flush process stdin: {}", e);
+                }
+            }
+        }
+    }
+}
+
+/// Execute Command and forward its stdout/stderr lines to `output` without logging them.
+///
+/// Returns how the process exited, no matter whether it succeeded.
+pub async fn execute_captured(
+    mut cmd: Command,
+    output: mpsc::UnboundedSender<String>,
+) -> Result<ExitStatus> {
+    log::debug!("Executing: {:?}", cmd);
+
+    cmd.stdout(Stdio::piped());
+    cmd.stderr(Stdio::piped());
+    let mut child = cmd
+        .kill_on_drop(true)
+        .spawn()
+        .context("Failed to spawn process.")?;
+
+    let stdout = child.stdout.take().unwrap(); // Safe because we setup stdout & stderr beforehand
+    let stderr = child.stderr.take().unwrap();
+
+    tokio::task::spawn(forward_progress(stdout, stderr, output));
+    child.wait().await.context("Failed to wait for process.")
+}
+
 #[derive(Debug)]
 pub enum ProcessUpdate {
     Line(String),
     Error(std::io::Error),
 }
 
-async fn print_progress(name: String, stdout: ChildStdout, stderr: ChildStderr) -> Result<()> {
-    use tokio::io::AsyncBufReadExt;
+async fn print_progress(
+    name: String,
+    stdout: ChildStdout,
+    stderr: ChildStderr,
+    output: Option<broadcast::Sender<String>>,
+) -> Result<()> {
     use tokio_stream::StreamExt;
 
-    // Merge stdout and stderr together
-    let reader = tokio_stream::StreamExt::merge(
-        LinesStream::new(BufReader::new(stdout).lines()),
-        LinesStream::new(BufReader::new(stderr).lines()),
-    );
+    let mut output_stream = progress(stdout, stderr);
 
-    let mut output_stream = reader.map(|x| match x {
-        Ok(x) => ProcessUpdate::Line(x),
-        Err(e) => ProcessUpdate::Error(e),
-    });
+    while let Some(progress) = output_stream.next().await {
+        match progress {
+            ProcessUpdate::Line(line) => {
+                let line = line.trim_start().trim_end();
+                log::info!("[{}] {}", name, line);
+                if let Some(output) = &output {
+                    // Nobody listening is not an error.
+                    let _ = output.send(line.to_string());
+                }
+            }
+            ProcessUpdate::Error(e) => {
+                log::error!("Failed to pipe process output: {}", e);
+                return Err(e.into());
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn forward_progress(
+    stdout: ChildStdout,
+    stderr: ChildStderr,
+    output: mpsc::UnboundedSender<String>,
+) -> Result<()> {
+    use tokio_stream::StreamExt;
+
+    let mut output_stream = progress(stdout, stderr);
 
     while let Some(progress) = output_stream.next().await {
         match progress {
             ProcessUpdate::Line(line) => {
-                log::info!("[{}] {}", name, line.trim_start().trim_end());
+                if output.send(line).is_err() {
+                    break;
+                }
             }
             ProcessUpdate::Error(e) => {
                 log::error!("Failed to pipe process output: {}", e);
@@ -77,6 +173,25 @@ async fn print_progress(name: String, stdout: ChildStdout, stderr: ChildStderr)
     Ok(())
 }
 
+/// Merges stdout and stderr together into a stream of lines.
+fn progress(
+    stdout: ChildStdout,
+    stderr: ChildStderr,
+) -> impl tokio_stream::Stream<Item = ProcessUpdate> {
+    use tokio::io::AsyncBufReadExt;
+    use tokio_stream::StreamExt;
+
+    let reader = tokio_stream::StreamExt::merge(
+        LinesStream::new(BufReader::new(stdout).lines()),
+        LinesStream::new(BufReader::new(stderr).lines()),
+    );
+
+    reader.map(|x| match x {
+        Ok(x) => ProcessUpdate::Line(x),
+        Err(e) => ProcessUpdate::Error(e),
+    })
+}
+
 pub async fn log_environment() -> Result<()> {
     let git_version = aquire_output(Command::new("git").arg("--version"))
         .await
@@ -101,3 +216,32 @@ pub async fn log_environment() -> Result<()> {
 
     Ok(())
 }
+
+/// Cuts `text` to at most `max_len` bytes, marking the cut with an ellipsis.
+pub fn truncate(text: &str, max_len: usize) -> String {
+    const ELLIPSIS: &str = "…";
+
+    if text.len() <= max_len {
+        return text.to_string();
+    }
+    let mut end = max_len.saturating_sub(ELLIPSIS.len());
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!("{}{}", &text[..end], ELLIPSIS)
+}
+
+/// The most recent of `lines` which fit into `max_len` bytes, joined by newlines.
+pub fn tail(lines: &[String], max_len: usize) -> String {
+    let mut output = Vec::new();
+    let mut len = 0;
+    for line in lines.iter().rev() {
+        len += line.len() + 1;
+        if len > max_len {
+            break;
+        }
+        output.push(line.as_str());
+    }
+    output.reverse();
+    output.join("\n")
+}